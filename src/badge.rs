@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+
+use crate::{
+    history,
+    models::{PlayerSettings, Status},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BadgeConfig {
+    // Text on the left side of the badge. Defaults to "%name"
+    #[serde(default = "default_label")]
+    pub label: String,
+
+    // Text on the right side of the badge when the server is up. Defaults to "%online/%max online"
+    #[serde(default = "default_template")]
+    pub template: String,
+
+    // Text on the right side of the badge when the server is down. Defaults to "offline"
+    #[serde(default = "default_offline")]
+    pub offline: String,
+
+    // Colors of the right side by the number of online players. The threshold with the highest number reached is used
+    #[serde(default = "default_colors")]
+    pub colors: Vec<ColorThreshold>,
+
+    // Color of the right side when the server is down. Defaults to red
+    #[serde(default = "default_offline_color")]
+    pub offline_color: String,
+
+    // Color of the left side. Defaults to gray
+    #[serde(default = "default_label_color")]
+    pub label_color: String,
+
+    // Directory the badges are written to as <target>.svg whenever they change. Not written when null
+    pub path: Option<String>,
+}
+
+impl Default for BadgeConfig {
+    fn default() -> Self {
+        Self {
+            label: default_label(),
+            template: default_template(),
+            offline: default_offline(),
+            colors: default_colors(),
+            offline_color: default_offline_color(),
+            label_color: default_label_color(),
+            path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ColorThreshold {
+    // Number of online players from which the color is used
+    pub online: u32,
+    // Any SVG color, e.g. "#4c1" or "green"
+    pub color: String,
+}
+
+fn default_label() -> String {
+    "%name".to_owned()
+}
+
+fn default_template() -> String {
+    "%online/%max online".to_owned()
+}
+
+fn default_offline() -> String {
+    "offline".to_owned()
+}
+
+fn default_colors() -> Vec<ColorThreshold> {
+    vec![
+        ColorThreshold { online: 0, color: "#dfb317".to_owned() },
+        ColorThreshold { online: 1, color: "#4c1".to_owned() },
+    ]
+}
+
+fn default_offline_color() -> String {
+    "#e05d44".to_owned()
+}
+
+fn default_label_color() -> String {
+    "#555".to_owned()
+}
+
+static CONFIG: OnceCell<BadgeConfig> = OnceCell::new();
+
+// Latest badge of every target that was pinged, keyed by the target name
+static BADGES: Lazy<Mutex<HashMap<String, Arc<str>>>> = Lazy::new(Default::default);
+
+pub fn init(config: &BadgeConfig) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = config.path.as_ref() {
+        fs::create_dir_all(path)?;
+    }
+    CONFIG.set(config.clone()).ok();
+    Ok(())
+}
+
+// Renders the badge of the target, writing it to the badge directory when it changed
+pub fn update(status: &Status, up: bool) {
+    let config = CONFIG.get_or_init(BadgeConfig::default);
+    let badge: Arc<str> = render(config, status, up).into();
+    let target = &status.target.name;
+    {
+        let mut badges = BADGES.lock().unwrap();
+        if badges.get(target) == Some(&badge) {
+            return;
+        }
+        badges.insert(target.clone(), badge.clone());
+    }
+    if let Some(path) = config.path.as_ref() {
+        if let Err(err) = write(path, target, &badge) {
+            log::error!("failed to write the badge of {}: {}", target, err);
+        }
+    }
+}
+
+// Latest badge of the target, None until the target was pinged
+pub fn get(target: &str) -> Option<Arc<str>> {
+    BADGES.lock().unwrap().get(target).cloned()
+}
+
+// The badge replaces the file at once so it is never served half written
+fn write(path: &str, target: &str, badge: &str) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(path).join(format!("{}.svg", history::file_name(target)));
+    let temporary = path.with_extension("svg.tmp");
+    fs::write(&temporary, badge)?;
+    fs::rename(&temporary, &path)?;
+    Ok(())
+}
+
+fn render(config: &BadgeConfig, status: &Status, up: bool) -> String {
+    let label = status.format(&config.label, &PlayerSettings::separated(", "));
+    let (message, color) = match up {
+        true => {
+            let color = config.colors.iter()
+                .filter(|threshold| threshold.online <= status.players.online)
+                .max_by_key(|threshold| threshold.online)
+                .map(|threshold| threshold.color.as_str())
+                .unwrap_or(&config.offline_color);
+            (status.format(&config.template, &PlayerSettings::separated(", ")), color)
+        },
+        false => (status.format(&config.offline, &PlayerSettings::separated(", ")), config.offline_color.as_str()),
+    };
+    svg(&label, &message, &config.label_color, color)
+}
+
+// Flat badge in the style of shields.io
+fn svg(label: &str, message: &str, label_color: &str, color: &str) -> String {
+    let label_width = (text_width(label) + 10.0).round();
+    let message_width = (text_width(message) + 10.0).round();
+    let width = label_width + message_width;
+    let (label, message) = (escape(label), escape(message));
+    let mut svg = String::new();
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="20" role="img" aria-label="{l}: {m}">"#, w = width, l = label, m = message).unwrap();
+    writeln!(svg, r#"<title>{}: {}</title>"#, label, message).unwrap();
+    svg.push_str(r##"<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>"##);
+    svg.push('\n');
+    writeln!(svg, r##"<clipPath id="r"><rect width="{}" height="20" rx="3" fill="#fff"/></clipPath>"##, width).unwrap();
+    writeln!(svg, r#"<g clip-path="url(#r)"><rect width="{}" height="20" fill="{}"/><rect x="{}" width="{}" height="20" fill="{}"/><rect width="{}" height="20" fill="url(#s)"/></g>"#,
+        label_width, escape(label_color), label_width, message_width, escape(color), width).unwrap();
+    svg.push_str(r##"<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">"##);
+    svg.push('\n');
+    for (text, x) in [(&label, label_width / 2.0), (&message, label_width + message_width / 2.0)] {
+        writeln!(svg, r##"<text x="{x}" y="15" fill="#010101" fill-opacity=".3">{t}</text><text x="{x}" y="14">{t}</text>"##, x = x, t = text).unwrap();
+    }
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+// Approximate width of the text in 11px Verdana, as the badge is sized without the font
+fn text_width(text: &str) -> f64 {
+    text.chars().map(|c| match c {
+        'i' | 'j' | 'l' | '!' | '|' | '.' | ',' | ':' | ';' | '\'' => 3.5,
+        'f' | 'r' | 't' | 'I' | ' ' | '(' | ')' | '[' | ']' | '/' => 4.5,
+        'm' | 'w' | 'M' | 'W' | '%' => 10.5,
+        'A'..='Z' => 7.5,
+        _ => 7.0,
+    }).sum()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Description, Players, Version};
+
+    fn status(online: u32) -> Status {
+        let mut status = Status::offline("play.example.net".to_owned(), 25565, Default::default());
+        status.version = Version { name: "1.20.1".to_owned(), protocol: 763 };
+        status.players = Players { max: 50, online, sample: None };
+        status.description = Description { text: "A <Minecraft> Server".to_owned() };
+        status
+    }
+
+    #[test]
+    fn renders_badges() {
+        let config = BadgeConfig { label: "%host".to_owned(), ..Default::default() };
+        let badge = render(&config, &status(12), true);
+        assert!(badge.contains(">play.example.net</text>"));
+        assert!(badge.contains(">12/50 online</text>"));
+        assert!(badge.contains(r##"fill="#4c1""##));
+        assert!(render(&config, &status(0), true).contains(r##"fill="#dfb317""##));
+        let offline = render(&config, &status(0), false);
+        assert!(offline.contains(">offline</text>"));
+        assert!(offline.contains(r##"fill="#e05d44""##));
+
+        let config = BadgeConfig { template: "%description".to_owned(), ..Default::default() };
+        assert!(render(&config, &status(1), true).contains(">A &lt;Minecraft&gt; Server</text>"));
+    }
+}
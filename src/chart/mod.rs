@@ -0,0 +1,298 @@
+use std::{error::Error, fmt::Write};
+
+use crate::{
+    history::{self, HistoryConfig},
+    time,
+};
+
+mod font;
+mod png;
+
+pub const DEFAULT_WIDTH: u32 = 800;
+pub const DEFAULT_HEIGHT: u32 = 300;
+
+// Colors of the series, cycled through when there are more targets
+const COLORS: [[u8; 3]; 6] = [[0x3b, 0x82, 0xf6], [0xf5, 0x9e, 0x0b], [0x10, 0xb9, 0x81], [0xef, 0x44, 0x44], [0x8b, 0x5c, 0xf6], [0x78, 0x5a, 0x3c]];
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+const GRID: [u8; 3] = [0xe5, 0xe7, 0xeb];
+const TEXT: [u8; 3] = [0x37, 0x41, 0x51];
+
+// Steps (in seconds) between the time labels, the smallest one resulting in at most 8 labels is used
+const TIME_STEPS: [u64; 12] = [60, 300, 900, 1800, 3600, 7200, 10800, 21600, 43200, 86400, 172800, 604800];
+
+#[derive(Debug)]
+pub struct Point {
+    pub time: u64,
+    // Average number of online players, None when the server was down
+    pub online: Option<f64>,
+    pub max: u32,
+}
+
+#[derive(Debug)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<Point>,
+}
+
+// Number of online and maximum players of one or more targets over a period
+#[derive(Debug)]
+pub struct Chart {
+    pub from: u64,
+    pub to: u64,
+    // Time (in seconds) covered by a point. Lines are interrupted where points are missing
+    pub resolution: u64,
+    pub series: Vec<Series>,
+}
+
+impl Chart {
+    // Reads the history of the targets, merging the samples so there is a point every few pixels
+    pub fn from_history(config: &HistoryConfig, targets: &[&str], from: u64, to: u64, width: u32) -> Result<Self, Box<dyn Error>> {
+        let resolution = (to.saturating_sub(from) / (width as u64 / 2).max(1)).max(1);
+        let mut series = Vec::new();
+        for target in targets {
+            let history = history::query(config, target, from, to, Some(resolution))?;
+            let points = history.samples.iter().map(|sample| Point {
+                time: sample.time,
+                online: (sample.uptime > 0.0).then_some(sample.online),
+                max: sample.max,
+            }).collect();
+            series.push(Series { name: target.to_string(), points });
+        }
+        Ok(Self { from, to: to.max(from + 1), resolution, series })
+    }
+
+    pub fn svg(&self, width: u32, height: u32) -> String {
+        let layout = Layout::new(self, width, height);
+        let mut svg = String::new();
+        writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#, w = width, h = height).unwrap();
+        writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, width, height, hex(BACKGROUND)).unwrap();
+        for (value, y) in layout.value_ticks() {
+            writeln!(svg, r#"<line x1="{}" y1="{y:.1}" x2="{}" y2="{y:.1}" stroke="{}"/>"#, layout.left, layout.right, hex(GRID), y = y).unwrap();
+            writeln!(svg, r#"<text x="{}" y="{:.1}" text-anchor="end" fill="{}">{}</text>"#, layout.left - 4.0, y + 4.0, hex(TEXT), value).unwrap();
+        }
+        for (label, x) in layout.time_ticks() {
+            writeln!(svg, r#"<text x="{:.1}" y="{}" text-anchor="middle" fill="{}">{}</text>"#, x, layout.bottom + 14.0, hex(TEXT), label).unwrap();
+        }
+        for (index, series) in self.series.iter().enumerate() {
+            let color = hex(COLORS[index % COLORS.len()]);
+            for line in layout.lines(series, |point| Some(point.max as f64)) {
+                writeln!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="1" stroke-dasharray="4 3"/>"#, points(&line), color).unwrap();
+            }
+            for line in layout.lines(series, |point| point.online) {
+                writeln!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2" stroke-linejoin="round"/>"#, points(&line), color).unwrap();
+            }
+            let x = layout.left + index as f64 * 120.0;
+            writeln!(svg, r#"<rect x="{}" y="6" width="10" height="10" fill="{}"/>"#, x, color).unwrap();
+            writeln!(svg, r#"<text x="{}" y="15" fill="{}">{}</text>"#, x + 14.0, hex(TEXT), escape(&series.name)).unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn png(&self, width: u32, height: u32) -> Vec<u8> {
+        let layout = Layout::new(self, width, height);
+        let mut palette = vec![BACKGROUND, GRID, TEXT];
+        palette.extend_from_slice(&COLORS);
+        let mut canvas = Canvas::new(width as usize, height as usize);
+        for (value, y) in layout.value_ticks() {
+            canvas.line((layout.left, y), (layout.right, y), 1, 1, None);
+            let text = value.to_string();
+            let x = layout.left - 4.0 - canvas.text_width(&text) as f64;
+            canvas.text(x, y - 3.0, &text, 2);
+        }
+        for (label, x) in layout.time_ticks() {
+            canvas.text(x - canvas.text_width(&label) as f64 / 2.0, layout.bottom + 5.0, &label, 2);
+        }
+        for (index, series) in self.series.iter().enumerate() {
+            let color = 3 + (index % COLORS.len()) as u8;
+            for line in layout.lines(series, |point| Some(point.max as f64)) {
+                for pair in line.windows(2) {
+                    canvas.line(pair[0], pair[1], color, 1, Some(4));
+                }
+            }
+            for line in layout.lines(series, |point| point.online) {
+                for pair in line.windows(2) {
+                    canvas.line(pair[0], pair[1], color, 2, None);
+                }
+                if let [point] = line.as_slice() {
+                    canvas.line(*point, *point, color, 2, None);
+                }
+            }
+            let x = layout.left + index as f64 * 120.0;
+            canvas.rect(x as usize, 6, 10, 10, color);
+            canvas.text(x + 14.0, 8.0, &series.name, 2);
+        }
+        png::encode(width, height, &palette, &canvas.pixels)
+    }
+}
+
+// Position of the plot area and the scales of the axes
+struct Layout {
+    from: u64,
+    to: u64,
+    resolution: u64,
+    left: f64,
+    right: f64,
+    top: f64,
+    bottom: f64,
+    max: f64,
+}
+
+impl Layout {
+    fn new(chart: &Chart, width: u32, height: u32) -> Self {
+        let highest = chart.series.iter()
+            .flat_map(|series| series.points.iter())
+            .map(|point| point.online.unwrap_or_default().max(point.max as f64))
+            .fold(0.0, f64::max);
+        Self {
+            from: chart.from,
+            to: chart.to,
+            resolution: chart.resolution,
+            left: 40.0,
+            right: width as f64 - 12.0,
+            top: 26.0,
+            bottom: height as f64 - 20.0,
+            max: nice_ceiling(highest),
+        }
+    }
+
+    fn x(&self, time: u64) -> f64 {
+        self.left + (time.saturating_sub(self.from)) as f64 / (self.to - self.from) as f64 * (self.right - self.left)
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.bottom - value / self.max * (self.bottom - self.top)
+    }
+
+    // Values of the horizontal grid lines and their positions
+    fn value_ticks(&self) -> Vec<(u64, f64)> {
+        let step = (self.max / 4.0).max(1.0);
+        (0..=4).map(|index| index as f64 * step)
+            .filter(|value| *value <= self.max)
+            .map(|value| (value as u64, self.y(value)))
+            .collect()
+    }
+
+    // Labels of the time axis and their positions
+    fn time_ticks(&self) -> Vec<(String, f64)> {
+        let range = self.to - self.from;
+        let step = TIME_STEPS.iter().copied().find(|step| range / step <= 8).unwrap_or(range.max(1));
+        let first = self.from.div_ceil(step) * step;
+        (first..=self.to).step_by(step as usize)
+            .map(|time| {
+                let formatted = time::format_time(time);
+                // Dates for long periods, times otherwise
+                let label = if step >= 86400 { formatted[5..10].to_owned() } else { formatted[11..16].to_owned() };
+                (label, self.x(time))
+            })
+            .collect()
+    }
+
+    // Positions of the points of a series, split where the value or the points are missing
+    fn lines(&self, series: &Series, value: impl Fn(&Point) -> Option<f64>) -> Vec<Vec<(f64, f64)>> {
+        let mut lines = vec![Vec::new()];
+        let mut last_time = None;
+        for point in series.points.iter() {
+            if last_time.is_some_and(|time| point.time - time > self.resolution * 2) && !lines.last().unwrap().is_empty() {
+                lines.push(Vec::new());
+            }
+            last_time = Some(point.time);
+            match value(point) {
+                Some(value) => lines.last_mut().unwrap().push((self.x(point.time), self.y(value))),
+                None if lines.last().is_some_and(|line| !line.is_empty()) => lines.push(Vec::new()),
+                None => {},
+            }
+        }
+        lines.retain(|line| !line.is_empty());
+        lines
+    }
+}
+
+// Smallest value of 1, 2 or 5 times a power of ten that isn't lower than the value
+fn nice_ceiling(value: f64) -> f64 {
+    if value <= 1.0 {
+        return 4.0;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|factor| factor * magnitude)
+        .find(|nice| *nice >= value)
+        .unwrap_or(value)
+        .max(4.0)
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+fn points(line: &[(f64, f64)]) -> String {
+    line.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect::<Vec<_>>().join(" ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Image of palette indices
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, height, pixels: vec![0; width * height] }
+    }
+
+    fn set(&mut self, x: i64, y: i64, color: u8) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    fn rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x as i64, y as i64, color);
+            }
+        }
+    }
+
+    // Draws a line of the thickness, leaving gaps every few pixels when dashed
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), color: u8, thickness: i64, dash: Option<usize>) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as usize;
+        for step in 0..=steps {
+            if dash.is_some_and(|dash| step / dash % 2 == 1) {
+                continue;
+            }
+            let progress = step as f64 / steps as f64;
+            let x = (from.0 + (to.0 - from.0) * progress).round() as i64;
+            let y = (from.1 + (to.1 - from.1) * progress).round() as i64;
+            for offset_y in 0..thickness {
+                for offset_x in 0..thickness {
+                    self.set(x + offset_x - thickness / 2, y + offset_y - thickness / 2, color);
+                }
+            }
+        }
+    }
+
+    fn text_width(&self, text: &str) -> usize {
+        text.chars().count() * (font::WIDTH + 1)
+    }
+
+    fn text(&mut self, x: f64, y: f64, text: &str, color: u8) {
+        let (x, y) = (x.round() as i64, y.round() as i64);
+        for (index, c) in text.chars().enumerate() {
+            let glyph = font::glyph(c);
+            let left = x + (index * (font::WIDTH + 1)) as i64;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..font::WIDTH {
+                    if bits & (1 << (font::WIDTH - 1 - column)) != 0 {
+                        self.set(left + column as i64, y + row as i64, color);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Minimal PNG encoder for palette images, compressing the runs of pixels charts consist of
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Encodes an image with one palette index per pixel
+pub fn encode(width: u32, height: u32, palette: &[[u8; 3]], pixels: &[u8]) -> Vec<u8> {
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per pixel, indexed color, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"PLTE", &palette.concat());
+
+    // Every scanline starts with the filter type, 0 for none
+    let stride = width as usize + 1;
+    let mut raw = Vec::with_capacity(stride * height as usize);
+    for row in pixels.chunks(width as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    chunk(&mut png, b"IDAT", &zlib(&raw, stride));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Zlib stream of a single fixed Huffman block, matching runs of the previous byte and the previous row
+fn zlib(data: &[u8], stride: usize) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    // Final block, fixed Huffman codes
+    bits.write(1, 1);
+    bits.write(1, 2);
+    let mut position = 0;
+    while position < data.len() {
+        let best = [1, stride].into_iter()
+            .filter(|distance| *distance <= position && *distance <= 32768)
+            .map(|distance| (distance, match_length(data, position, distance)))
+            .max_by_key(|(_, length)| *length);
+        match best {
+            Some((distance, length)) if length >= 3 => {
+                write_length(&mut bits, length);
+                write_distance(&mut bits, distance);
+                position += length;
+            },
+            _ => {
+                write_literal(&mut bits, data[position] as u16);
+                position += 1;
+            },
+        }
+    }
+    // End of block
+    write_literal(&mut bits, 256);
+
+    let mut output = vec![0x78, 0x01];
+    output.extend(bits.finish());
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn match_length(data: &[u8], position: usize, distance: usize) -> usize {
+    let mut length = 0;
+    while length < 258 && position + length < data.len() && data[position + length] == data[position + length - distance] {
+        length += 1;
+    }
+    length
+}
+
+fn write_literal(bits: &mut BitWriter, value: u16) {
+    match value {
+        0..=143 => bits.write_code(0x30 + value as u32, 8),
+        144..=255 => bits.write_code(0x190 + (value as u32 - 144), 9),
+        256..=279 => bits.write_code(value as u32 - 256, 7),
+        _ => bits.write_code(0xc0 + (value as u32 - 280), 8),
+    }
+}
+
+const LENGTH_BASES: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u32; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASES: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u32; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+fn write_length(bits: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASES.iter().rposition(|base| *base <= length).unwrap();
+    write_literal(bits, 257 + index as u16);
+    bits.write((length - LENGTH_BASES[index]) as u32, LENGTH_EXTRA[index]);
+}
+
+fn write_distance(bits: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASES.iter().rposition(|base| *base <= distance).unwrap();
+    bits.write_code(index as u32, 5);
+    bits.write((distance - DISTANCE_BASES[index]) as u32, DISTANCE_EXTRA[index]);
+}
+
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    // Writes the value starting with the least significant bit
+    fn write(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Writes a Huffman code, which starts with the most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    config::Edition,
+    models::InternalError,
+    time,
+};
+
+pub const USAGE: &str = "Usage: {program} [options] [<hostname> [port]]
+       {program} tui [options] [<hostname> [port]]
+       {program} status [options] <hostname> [port]
+       {program} check [options] <hostname> [port]
+       {program} history [options] <target>
+       {program} export [options] <target>
+
+Commands:
+    tui                             Monitor the targets in a full-screen terminal dashboard.
+                                    Keys: up/down select a target, r pings it, m mutes its notifications,
+                                    page up/down scroll the events and q quits
+    status                          Ping the server once and print its status.
+                                    Exits with 0 when the server is up, 1 when it is unreachable and 2 on a protocol error
+    check                           Ping the server once and report the result as a Nagios/Icinga monitoring plugin
+    history                         Print the recorded history of a target
+    export                          Export the recorded history of a target as CSV or JSON Lines
+
+Options:
+    -c, --config <path>             Path to the configuration file. Defaults to $MC_PING_CONFIG or ./mc-ping.json
+    -i, --interval <seconds>        Time between pings
+        --ping-timeout <seconds>    Time after which a ping is considered failed
+        --request-timeout <seconds> Time after which a notification request is considered failed
+    -l, --log-level <filters>       Log level or filters in the RUST_LOG format
+    -h, --help                      Print this message
+
+Status and check options:
+    -e, --edition <edition>         Minecraft edition of the server: java or bedrock. Defaults to java
+
+Status options:
+    -f, --format <format>           Output format: table, json or template. Defaults to table
+    -t, --template <template>       Template printed with the template format. Implies --format template
+
+Check options:
+        --warning-latency <ms>      Latency reaching which results in a WARNING
+        --critical-latency <ms>     Latency reaching which results in a CRITICAL
+        --warning-response <ms>     Response time reaching which results in a WARNING
+        --critical-response <ms>    Response time reaching which results in a CRITICAL
+        --warning-players <ratio>   Player fill ratio (0.0 - 1.0) reaching which results in a WARNING
+        --critical-players <ratio>  Player fill ratio (0.0 - 1.0) reaching which results in a CRITICAL
+
+History and export options:
+        --target <name>             Name of the target
+        --from <time>               Start of the period: Unix time, a UTC date like 2024-05-01T18:00 or a time ago like 7d.
+                                    Defaults to 1d
+        --to <time>                 End of the period. Defaults to now
+        --resolution <duration>     Period merged into a single sample, e.g. 5m, 1h or 1d
+
+History options:
+    -f, --format <format>           Output format: table or json. Defaults to table
+
+Export options:
+    -f, --format <format>           Output format: csv or jsonl. Defaults to csv
+        --events                    Export the events instead of the samples";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    // Monitor the targets and send notifications
+    #[default]
+    Monitor,
+    // Ping a server once and print its status
+    Status,
+    // Ping a server once and report the result as a monitoring plugin
+    Check,
+    // Monitor the targets in a terminal dashboard
+    Tui,
+    // Print the recorded history of a target
+    History,
+    // Export the recorded history of a target
+    Export,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Template,
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Default)]
+pub struct Thresholds {
+    pub warning_latency: Option<u64>,
+    pub critical_latency: Option<u64>,
+    pub warning_response: Option<u64>,
+    pub critical_response: Option<u64>,
+    pub warning_players: Option<f64>,
+    pub critical_players: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub program: String,
+    pub command: Command,
+    pub config: Option<String>,
+    pub interval: Option<u64>,
+    pub ping_timeout: Option<u64>,
+    pub request_timeout: Option<u64>,
+    pub log_level: Option<String>,
+    pub help: bool,
+    pub edition: Edition,
+    pub format: Format,
+    pub template: Option<String>,
+    pub thresholds: Thresholds,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub resolution: Option<u64>,
+    pub events: bool,
+    // Hostname of the server, or the target name for the history command
+    pub hostname: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, InternalError> {
+        let mut parsed = Args {
+            program: args.next().unwrap_or_else(|| "mc-ping".to_owned()),
+            ..Default::default()
+        };
+        let mut positional = Vec::new();
+        let mut format = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => parsed.config = Some(value(&arg, args.next())?),
+                "-i" | "--interval" => parsed.interval = Some(seconds(&arg, args.next())?),
+                "--ping-timeout" => parsed.ping_timeout = Some(seconds(&arg, args.next())?),
+                "--request-timeout" => parsed.request_timeout = Some(number(&arg, args.next())?),
+                "-l" | "--log-level" => parsed.log_level = Some(value(&arg, args.next())?),
+                "-h" | "--help" => parsed.help = true,
+                "-e" | "--edition" => parsed.edition = match value(&arg, args.next())?.as_str() {
+                    "java" => Edition::Java,
+                    "bedrock" => Edition::Bedrock,
+                    other => return Err(InternalError::new(format!("unknown edition '{}'", other))),
+                },
+                "-f" | "--format" => format = Some(match value(&arg, args.next())?.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    "template" => Format::Template,
+                    "csv" => Format::Csv,
+                    "jsonl" => Format::Jsonl,
+                    other => return Err(InternalError::new(format!("unknown format '{}'", other))),
+                }),
+                "-t" | "--template" => {
+                    parsed.template = Some(value(&arg, args.next())?);
+                    format = Some(Format::Template);
+                },
+                "--warning-latency" => parsed.thresholds.warning_latency = Some(number(&arg, args.next())?),
+                "--critical-latency" => parsed.thresholds.critical_latency = Some(number(&arg, args.next())?),
+                "--warning-response" => parsed.thresholds.warning_response = Some(number(&arg, args.next())?),
+                "--critical-response" => parsed.thresholds.critical_response = Some(number(&arg, args.next())?),
+                "--warning-players" => parsed.thresholds.warning_players = Some(number(&arg, args.next())?),
+                "--critical-players" => parsed.thresholds.critical_players = Some(number(&arg, args.next())?),
+                "--from" => parsed.from = Some(time::parse_time(&value(&arg, args.next())?)?),
+                "--to" => parsed.to = Some(time::parse_time(&value(&arg, args.next())?)?),
+                "--resolution" => parsed.resolution = Some(time::parse_duration(&value(&arg, args.next())?)?),
+                "--target" => parsed.hostname = Some(value(&arg, args.next())?),
+                "--events" => parsed.events = true,
+                _ if arg.starts_with('-') => return Err(InternalError::new(format!("unknown option '{}'", arg))),
+                "tui" if positional.is_empty() && parsed.command == Command::Monitor => parsed.command = Command::Tui,
+                "status" if positional.is_empty() && parsed.command == Command::Monitor => parsed.command = Command::Status,
+                "check" if positional.is_empty() && parsed.command == Command::Monitor => parsed.command = Command::Check,
+                "history" if positional.is_empty() && parsed.command == Command::Monitor => parsed.command = Command::History,
+                "export" if positional.is_empty() && parsed.command == Command::Monitor => parsed.command = Command::Export,
+                _ => positional.push(arg),
+            }
+        }
+        let formats: &[Format] = match parsed.command {
+            Command::Monitor | Command::Status | Command::Check => &[Format::Table, Format::Json, Format::Template],
+            Command::Tui => &[Format::Table],
+            Command::History => &[Format::Table, Format::Json],
+            Command::Export => &[Format::Csv, Format::Jsonl],
+        };
+        parsed.format = match format {
+            Some(format) if !formats.contains(&format) => {
+                return Err(InternalError::new(format!("the {:?} format is not supported by this command", format).to_lowercase()));
+            },
+            Some(format) => format,
+            None => formats[0],
+        };
+
+        // The history commands take the name of a target instead of a hostname and port
+        let named = matches!(parsed.command, Command::History | Command::Export);
+        let mut positional = positional.into_iter();
+        if !named || parsed.hostname.is_none() {
+            parsed.hostname = positional.next();
+        }
+        if named {
+            if let Some(extra) = positional.next() {
+                return Err(InternalError::new(format!("unexpected argument '{}'", extra)));
+            }
+        }
+        if let Some(raw) = positional.next() {
+            match raw.parse::<u16>() {
+                Ok(port) => parsed.port = Some(port),
+                Err(err) => return Err(InternalError::new(format!("'{}' is not a valid port number: {}", raw, err))),
+            }
+        }
+        if let Some(extra) = positional.next() {
+            return Err(InternalError::new(format!("unexpected argument '{}'", extra)));
+        }
+        if named && parsed.hostname.is_none() && !parsed.help {
+            return Err(InternalError::new("a target name is required"));
+        }
+        if !matches!(parsed.command, Command::Monitor | Command::Tui) && parsed.hostname.is_none() && !parsed.help {
+            return Err(InternalError::new("a hostname is required"));
+        }
+        Ok(parsed)
+    }
+
+    pub fn usage(&self) -> String {
+        USAGE.replace("{program}", &self.program)
+    }
+}
+
+fn value<S: Display>(option: S, value: Option<String>) -> Result<String, InternalError> {
+    value.ok_or_else(|| InternalError::new(format!("missing value for '{}'", option)))
+}
+
+fn number<S: Display, N: FromStr>(option: S, raw: Option<String>) -> Result<N, InternalError>
+    where N::Err: Display
+{
+    let raw = value(&option, raw)?;
+    raw.parse().map_err(|err| InternalError::new(format!("'{}' is not a valid value for '{}': {}", raw, option, err)))
+}
+
+// Number of seconds which can't be 0, like the interval
+fn seconds<S: Display>(option: S, raw: Option<String>) -> Result<u64, InternalError> {
+    match number(&option, raw)? {
+        0 => Err(InternalError::new(format!("'{}' must be at least 1 second", option))),
+        seconds => Ok(seconds),
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fmt::Display,
+    process::ExitCode,
+};
+
+use crate::{
+    cli::Args,
+    ping::{Address, ErrorKind},
+};
+
+// Monitoring plugin states, ordered by severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+impl From<State> for ExitCode {
+    fn from(state: State) -> Self {
+        ExitCode::from(state as u8)
+    }
+}
+
+pub async fn run(args: &Args) -> ExitCode {
+    let address = Address::resolve(super::target(args));
+    let status = match address.ping(super::timeout(args)).await {
+        Ok(status) => status,
+        Err(err) => {
+            let state = match ErrorKind::of(err.as_ref()) {
+                ErrorKind::Protocol => State::Unknown,
+                ErrorKind::Unreachable | ErrorKind::Timeout => State::Critical,
+            };
+            println!("MINECRAFT {} - {}:{}: {}", state, address.host, address.port, err);
+            return state.into()
+        },
+    };
+
+    let thresholds = &args.thresholds;
+    let fill = if status.players.max > 0 {
+        status.players.online as f64 / status.players.max as f64
+    } else {
+        0.0
+    };
+    let state = [
+        evaluate(status.latency as f64, thresholds.warning_latency.map(|ms| ms as f64), thresholds.critical_latency.map(|ms| ms as f64)),
+        evaluate(status.response_time as f64, thresholds.warning_response.map(|ms| ms as f64), thresholds.critical_response.map(|ms| ms as f64)),
+        evaluate(fill, thresholds.warning_players, thresholds.critical_players),
+    ].into_iter().max().unwrap_or(State::Ok);
+
+    // Player thresholds are reported as player counts
+    let players = |ratio: Option<f64>| ratio.map(|ratio| (ratio * status.players.max as f64).round().to_string()).unwrap_or_default();
+    let milliseconds = |ms: Option<u64>| ms.map(|ms| ms.to_string()).unwrap_or_default();
+    println!(
+        "MINECRAFT {} - {}/{} players ({:.0}% full), {}, latency {}ms, response time {}ms | players={};{};{};0;{} latency={}ms;{};{};0; response_time={}ms;{};{};0;",
+        state,
+        status.players.online, status.players.max, fill * 100.0, status.version.name, status.latency, status.response_time,
+        status.players.online, players(thresholds.warning_players), players(thresholds.critical_players), status.players.max,
+        status.latency, milliseconds(thresholds.warning_latency), milliseconds(thresholds.critical_latency),
+        status.response_time, milliseconds(thresholds.warning_response), milliseconds(thresholds.critical_response),
+    );
+    state.into()
+}
+
+fn evaluate(value: f64, warning: Option<f64>, critical: Option<f64>) -> State {
+    if critical.is_some_and(|critical| value >= critical) {
+        State::Critical
+    } else if warning.is_some_and(|warning| value >= warning) {
+        State::Warning
+    } else {
+        State::Ok
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    error::Error,
+    io::{self, Write},
+    process::ExitCode,
+};
+use serde::Serialize;
+
+use crate::{
+    cli::{Args, Format},
+    history::{EventRecord, HistoryConfig, Sample},
+    notify::EventKind,
+    time,
+};
+
+// Event with the changes it caused, as exported
+#[derive(Debug, Serialize)]
+struct ExportedEvent {
+    time: u64,
+    // Kind of the event, or "joined" and "left" for the players seen in the samples of the player list
+    event: &'static str,
+    online: u32,
+    max: u32,
+    // Player that joined or left
+    player: Option<String>,
+    // Net change of the number of online players since the previous event
+    change: Option<i64>,
+    // Seconds the server was down, for went_up events
+    downtime: Option<u64>,
+}
+
+pub fn run(args: &Args, config: &HistoryConfig) -> ExitCode {
+    let history = match super::read_history(args, config) {
+        Some(history) => history,
+        None => return ExitCode::FAILURE,
+    };
+    let mut output = io::stdout().lock();
+    let result = match args.events {
+        true => write_events(&mut output, &events(&history.events, &history.samples), args.format),
+        false => write_samples(&mut output, &history.samples, args.format),
+    };
+    match result.and_then(|_| output.flush().map_err(Into::into)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn events(records: &[EventRecord], samples: &[Sample]) -> Vec<ExportedEvent> {
+    let mut previous_online = None;
+    let mut went_down = None;
+    let mut events = records.iter().map(|record| {
+        let mut event = ExportedEvent {
+            time: record.time,
+            event: record.event.name(),
+            online: record.online,
+            max: record.max,
+            player: None,
+            change: None,
+            downtime: None,
+        };
+        match record.event {
+            EventKind::PlayersChanged => event.change = previous_online.map(|previous: u32| record.online as i64 - previous as i64),
+            EventKind::WentDown => went_down = Some(record.time),
+            EventKind::WentUp => event.downtime = went_down.take().map(|since| record.time.saturating_sub(since)),
+            EventKind::MotdChanged | EventKind::Report => {},
+        }
+        if record.event != EventKind::WentDown {
+            previous_online = Some(record.online);
+        }
+        event
+    }).collect::<Vec<_>>();
+    events.extend(player_changes(samples));
+    // Stable, so the players follow the event recorded at the same time
+    events.sort_by_key(|event| event.time);
+    events
+}
+
+// Players joining and leaving between consecutive samples of the player list. Failed pings are skipped,
+// so the players of a server going down and up again are compared with the ones seen before
+fn player_changes(samples: &[Sample]) -> Vec<ExportedEvent> {
+    let mut changes = Vec::new();
+    let mut previous: Option<&Sample> = None;
+    for sample in samples.iter().filter(|sample| sample.uptime > 0.0) {
+        if let Some(previous) = previous {
+            let change = |event, player: &String| ExportedEvent {
+                time: sample.time,
+                event,
+                online: sample.online.round() as u32,
+                max: sample.max,
+                player: Some(player.clone()),
+                change: None,
+                downtime: None,
+            };
+            changes.extend(sample.players.iter().filter(|player| !previous.players.contains(player)).map(|player| change("joined", player)));
+            changes.extend(previous.players.iter().filter(|player| !sample.players.contains(player)).map(|player| change("left", player)));
+        }
+        previous = Some(sample);
+    }
+    changes
+}
+
+fn write_samples(output: &mut impl Write, samples: &[Sample], format: Format) -> Result<(), Box<dyn Error>> {
+    if format == Format::Jsonl {
+        return write_lines(output, samples);
+    }
+    writeln!(output, "time,timestamp,pings,up,online,peak,max,latency")?;
+    for sample in samples {
+        writeln!(output, "{},{},{},{:.4},{:.2},{},{},{:.1}",
+            time::format_time(sample.time), sample.time, sample.count, sample.uptime, sample.online, sample.peak, sample.max, sample.latency)?;
+    }
+    Ok(())
+}
+
+fn write_events(output: &mut impl Write, events: &[ExportedEvent], format: Format) -> Result<(), Box<dyn Error>> {
+    if format == Format::Jsonl {
+        return write_lines(output, events);
+    }
+    writeln!(output, "time,timestamp,event,online,max,player,change,downtime")?;
+    for event in events {
+        let player = event.player.as_deref().map(csv_field).unwrap_or_default();
+        let change = event.change.map(|change| change.to_string()).unwrap_or_default();
+        let downtime = event.downtime.map(|downtime| downtime.to_string()).unwrap_or_default();
+        writeln!(output, "{},{},{},{},{},{},{},{}",
+            time::format_time(event.time), event.time, event.event, event.online, event.max, player, change, downtime)?;
+    }
+    Ok(())
+}
+
+// Quotes the field when it contains a separator, a quote or a line break, as the player names come from the server
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_owned(),
+    }
+}
+
+fn write_lines<T: Serialize>(output: &mut impl Write, values: &[T]) -> Result<(), Box<dyn Error>> {
+    for value in values {
+        serde_json::to_writer(&mut *output, value)?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: u64, uptime: f64, players: &[&str]) -> Sample {
+        Sample {
+            time,
+            count: 1,
+            uptime,
+            online: players.len() as f64,
+            peak: players.len() as u32,
+            peak_time: time,
+            max: 20,
+            latency: 0.0,
+            players: players.iter().map(|player| player.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn exports_players_joining_and_leaving() {
+        let samples = [
+            sample(10, 1.0, &["Alex"]),
+            // A join and a leave in the same interval
+            sample(20, 1.0, &["Steve"]),
+            sample(30, 0.0, &[]),
+            sample(40, 1.0, &["Steve", "Notch"]),
+        ];
+        let records = [EventRecord { time: 20, event: EventKind::PlayersChanged, online: 1, max: 20 }];
+        let events = events(&records, &samples).into_iter()
+            .map(|event| (event.time, event.event, event.player.unwrap_or_default()))
+            .collect::<Vec<_>>();
+        assert_eq!(events, [
+            (20, "players_changed", String::new()),
+            (20, "joined", "Steve".to_owned()),
+            (20, "left", "Alex".to_owned()),
+            (40, "joined", "Notch".to_owned()),
+        ]);
+        assert_eq!(csv_field("A, \"B\""), "\"A, \"\"B\"\"\"");
+    }
+}
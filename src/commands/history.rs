@@ -0,0 +1,69 @@
+use std::process::ExitCode;
+
+use crate::{
+    cli::{Args, Format},
+    history::{History, HistoryConfig},
+    time,
+};
+
+pub fn run(args: &Args, config: &HistoryConfig) -> ExitCode {
+    let history = match super::read_history(args, config) {
+        Some(history) => history,
+        None => return ExitCode::FAILURE,
+    };
+    match args.format {
+        Format::Json => match serde_json::to_string_pretty(&history) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                return ExitCode::FAILURE
+            }
+        },
+        _ => print_table(&history),
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_table(history: &History) {
+    let mut rows = vec![["Time (UTC)", "Pings", "Uptime", "Online", "Peak", "Max", "Latency"].map(str::to_owned)];
+    for sample in history.samples.iter() {
+        rows.push([
+            time::format_time(sample.time),
+            sample.count.to_string(),
+            format!("{:.1}%", sample.uptime * 100.0),
+            format!("{:.1}", sample.online),
+            sample.peak.to_string(),
+            sample.max.to_string(),
+            format!("{:.0} ms", sample.latency),
+        ]);
+    }
+    let mut widths = [0; 7];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let line = row.iter().zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    let summary = &history.summary;
+    println!();
+    println!("Uptime: {:.2}% of {} pings", summary.uptime_percent, summary.pings);
+    println!("Average online: {:.1}", summary.avg_online);
+    match summary.peak_time {
+        Some(peak_time) => println!("Peak: {} at {}", summary.peak, time::format_time(peak_time)),
+        None => println!("Peak: -"),
+    }
+    if !history.events.is_empty() {
+        println!();
+        println!("Events:");
+        for event in history.events.iter() {
+            println!("  {}  {}", time::format_time(event.time), event.event.name());
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::{
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    cli::Args,
+    config::{Target, Timeouts},
+    history::{History, HistoryConfig},
+    state,
+};
+
+pub mod status;
+pub mod check;
+pub mod history;
+pub mod export;
+pub mod tui;
+
+// Period of the history read when --from is not set
+const DEFAULT_PERIOD: u64 = 24 * 60 * 60;
+
+// Target described by the command line arguments
+fn target(args: &Args) -> Arc<Target> {
+    let mut target = Target::new(args.hostname.clone().unwrap_or_default(), args.port);
+    target.edition = args.edition;
+    Arc::new(target)
+}
+
+fn timeout(args: &Args) -> Duration {
+    Duration::from_secs(args.ping_timeout.unwrap_or(Timeouts::default().ping))
+}
+
+// History of the target named by the command line arguments, printing an error when it can't be read
+fn read_history(args: &Args, config: &HistoryConfig) -> Option<History> {
+    let target = args.hostname.clone().unwrap_or_default();
+    if !crate::history::exists(config, &target) {
+        eprintln!("Error: no history recorded for '{}' in {}", target, config.path);
+        return None
+    }
+    let to = args.to.unwrap_or_else(state::now);
+    let from = args.from.unwrap_or_else(|| to.saturating_sub(DEFAULT_PERIOD));
+    match crate::history::query(config, &target, from, to, args.resolution) {
+        Ok(history) => Some(history),
+        Err(err) => {
+            eprintln!("Error: failed to read the history of '{}': {}", target, err);
+            None
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::process::ExitCode;
+
+use crate::{
+    cli::{Args, Format},
+    models::{PlayerSettings, Status},
+    ping::{Address, ErrorKind},
+};
+
+const DEFAULT_TEMPLATE: &str = "%host:%port %online/%max";
+
+pub const UP: u8 = 0;
+pub const UNREACHABLE: u8 = 1;
+pub const PROTOCOL_ERROR: u8 = 2;
+
+pub async fn run(args: &Args) -> ExitCode {
+    let address = Address::resolve(super::target(args));
+    match address.ping(super::timeout(args)).await {
+        Ok(status) => {
+            match args.format {
+                Format::Json => match serde_json::to_string_pretty(&status) {
+                    Ok(json) => println!("{}", json),
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        return ExitCode::FAILURE
+                    }
+                },
+                Format::Template => println!("{}", status.format(args.template.as_deref().unwrap_or(DEFAULT_TEMPLATE), &PlayerSettings::separated(", "))),
+                _ => print_table(&status),
+            }
+            ExitCode::from(UP)
+        },
+        Err(err) => {
+            eprintln!("Error: {}:{}: {}", address.host, address.port, err);
+            match ErrorKind::of(err.as_ref()) {
+                ErrorKind::Protocol => ExitCode::from(PROTOCOL_ERROR),
+                ErrorKind::Unreachable | ErrorKind::Timeout => ExitCode::from(UNREACHABLE),
+            }
+        },
+    }
+}
+
+fn print_table(status: &Status) {
+    let mut rows = vec![
+        ("Server", format!("{}:{}", status.host, status.port)),
+        ("Version", format!("{} (protocol {})", status.version.name, status.version.protocol)),
+        ("Players", format!("{}/{}", status.players.online, status.players.max)),
+        ("Latency", format!("{} ms", status.latency)),
+        ("Description", status.description.text.clone()),
+    ];
+    let players = status.players.to_string(", ");
+    if !players.is_empty() {
+        rows.push(("Online", players));
+    }
+    let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or_default();
+    for (label, value) in rows {
+        let mut lines = value.lines();
+        println!("{:width$}  {}", label, lines.next().unwrap_or_default(), width = width);
+        for line in lines {
+            println!("{:width$}  {}", "", line, width = width);
+        }
+    }
+}
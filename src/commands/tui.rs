@@ -0,0 +1,324 @@
+use std::{
+    io::{self, Read, Write},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
+use async_std::{
+    channel::{self, Receiver},
+    future,
+    task,
+};
+
+use crate::{
+    events,
+    motd,
+    state::{self, TargetState},
+    time,
+};
+
+// Time between redraws while no key is pressed
+const TICK: Duration = Duration::from_millis(500);
+
+// Lines the event log scrolls by
+const SCROLL: usize = 5;
+
+const HELP: &str = " mc-ping  up/down select  r ping  m mute  page up/down scroll events  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Ping,
+    Mute,
+    Quit,
+}
+
+// Shows the state of the targets monitored in the background until quit
+pub async fn run() -> ExitCode {
+    let terminal = match Terminal::enter() {
+        Ok(terminal) => terminal,
+        Err(err) => {
+            eprintln!("Error: unable to set up the terminal: {}", err);
+            return ExitCode::FAILURE
+        }
+    };
+    let keys = read_keys();
+    let mut view = View::default();
+    loop {
+        let targets = state::all();
+        let (width, height) = terminal.size();
+        if let Err(err) = terminal.draw(&view.render(&targets, width, height)) {
+            drop(terminal);
+            eprintln!("Error: unable to draw the terminal UI: {}", err);
+            return ExitCode::FAILURE
+        }
+        let key = match future::timeout(TICK, keys.recv()).await {
+            Ok(Ok(key)) => key,
+            // Standard input was closed
+            Ok(Err(_)) => break,
+            Err(_) => continue,
+        };
+        let selected = targets.get(view.selected);
+        match key {
+            Key::Up => view.selected = view.selected.saturating_sub(1),
+            Key::Down => view.selected += 1,
+            Key::PageUp => view.scroll += SCROLL,
+            Key::PageDown => view.scroll = view.scroll.saturating_sub(SCROLL),
+            Key::Ping => if let Some(target) = selected {
+                state::request_ping(&target.name);
+            },
+            Key::Mute => if let Some(target) = selected {
+                state::set_muted(&target.name, !target.muted);
+            },
+            Key::Quit => break,
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+// Reads the keys on a thread of its own, as reading the standard input blocks
+fn read_keys() -> Receiver<Key> {
+    let (sender, receiver) = channel::unbounded();
+    thread::spawn(move || {
+        let mut input = io::stdin().lock();
+        let mut buffer = [0; 32];
+        while let Ok(length) = input.read(&mut buffer) {
+            if length == 0 {
+                break;
+            }
+            for key in parse_keys(&buffer[..length]) {
+                if task::block_on(sender.send(key)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let (key, length) = match rest {
+            [0x1b, b'[', b'A', ..] => (Some(Key::Up), 3),
+            [0x1b, b'[', b'B', ..] => (Some(Key::Down), 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Some(Key::PageUp), 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Some(Key::PageDown), 4),
+            // Other escape sequences are ignored, while a lone escape quits
+            [0x1b, b'[', ..] => (None, rest.len()),
+            [b'k', ..] => (Some(Key::Up), 1),
+            [b'j', ..] => (Some(Key::Down), 1),
+            [b'r', ..] => (Some(Key::Ping), 1),
+            [b'm', ..] => (Some(Key::Mute), 1),
+            [b'q', ..] | [0x03, ..] | [0x1b] => (Some(Key::Quit), 1),
+            _ => (None, 1),
+        };
+        keys.extend(key);
+        rest = &rest[length..];
+    }
+    keys
+}
+
+#[derive(Debug, Default)]
+struct View {
+    // Index of the selected target
+    selected: usize,
+    // First target shown in the table
+    offset: usize,
+    // Lines the event log is scrolled up by
+    scroll: usize,
+}
+
+impl View {
+    // Lines of the whole screen
+    fn render(&mut self, targets: &[TargetState], width: usize, height: usize) -> Vec<String> {
+        self.selected = self.selected.min(targets.len().saturating_sub(1));
+        let mut lines = vec![format!("\x1b[7m{:<width$}\x1b[0m", fit(HELP, width), width = width)];
+
+        // The table takes up to a third of the screen, scrolling to the selected target
+        let rows = targets.len().min((height.saturating_sub(4) / 3).max(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + rows {
+            self.offset = self.selected + 1 - rows;
+        }
+        lines.push(format!("\x1b[1m{}\x1b[0m", fit(&format!(" {:<24} {:<12} {:>9} {:>9}  {}", "Name", "State", "Players", "Latency", "Version"), width)));
+        for (index, target) in targets.iter().enumerate().skip(self.offset).take(rows) {
+            let style = match (index == self.selected, target.up) {
+                (true, _) => "\x1b[7m",
+                (false, false) => "\x1b[31m",
+                (false, true) => "",
+            };
+            lines.push(format!("{}{:<width$}\x1b[0m", style, fit(&row(target), width), width = width));
+        }
+
+        let mut detail = match targets.get(self.selected) {
+            Some(target) => {
+                lines.push(title(&format!("{} ({}:{})", target.name, target.host, target.port), width));
+                details(target, width)
+            },
+            None => {
+                lines.push(title("No targets", width));
+                Vec::new()
+            },
+        };
+        detail.truncate(height.saturating_sub(lines.len() + 2));
+        lines.extend(detail);
+
+        lines.push(title("Events", width));
+        let space = height.saturating_sub(lines.len());
+        let events = events::recent();
+        self.scroll = self.scroll.min(events.len().saturating_sub(space));
+        let end = events.len() - self.scroll;
+        for record in events[end.saturating_sub(space)..end].iter() {
+            let change = format!("{}/{}", record.status.players.online, record.status.players.max);
+            lines.push(fit(&format!(" {}  {:<24} {:<16} {}", time::format_time(record.time), record.target, record.event.name(), change), width));
+        }
+        lines.truncate(height);
+        lines
+    }
+}
+
+fn row(target: &TargetState) -> String {
+    let mut state = match (target.last_checked, target.up) {
+        (None, _) => "pending",
+        (Some(_), true) => "up",
+        (Some(_), false) => "down",
+    }.to_owned();
+    if target.muted {
+        state.push_str(" muted");
+    }
+    let status = target.status.as_ref().filter(|_| target.up);
+    let players = status.map(|status| format!("{}/{}", status.players.online, status.players.max)).unwrap_or_else(|| "-".to_owned());
+    let latency = status.map(|status| format!("{} ms", status.latency)).unwrap_or_else(|| "-".to_owned());
+    let version = status.map(|status| motd::strip(&status.version.name)).unwrap_or_default();
+    format!(" {:<24} {:<12} {:>9} {:>9}  {}", fit(&target.name, 24), state, players, latency, version)
+}
+
+// Lines of the detail pane of the selected target
+fn details(target: &TargetState, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let inner = width.saturating_sub(1);
+    if let Some(status) = target.status.as_ref() {
+        lines.extend(motd::ansi(&status.description.text, inner).into_iter().map(|line| format!(" {}", line)));
+        lines.push(fit(&format!(" Version: {} (protocol {})  Latency: {} ms  Response time: {} ms",
+            motd::strip(&status.version.name), status.version.protocol, status.latency, status.response_time), width));
+    }
+    let checked = |time: Option<u64>| time.map(time::format_time).unwrap_or_else(|| "never".to_owned());
+    lines.push(fit(&format!(" Last checked: {}  Last success: {}  Notifications: {}",
+        checked(target.last_checked), checked(target.last_success), if target.muted { "muted" } else { "on" }), width));
+    if let Some(error) = target.error.as_ref().filter(|_| !target.up) {
+        lines.push(format!("\x1b[31m{}\x1b[0m", fit(&format!(" Error: {}", error), width)));
+    }
+    if let Some(status) = target.status.as_ref() {
+        let names = status.players.sample.iter().flatten().map(|player| motd::strip(&player.name)).collect::<Vec<_>>();
+        let mut line = format!(" Players ({}/{}):", status.players.online, status.players.max);
+        for name in names {
+            if line.chars().count() + name.chars().count() + 1 > inner {
+                lines.push(fit(&line, width));
+                line = " ".to_owned();
+            }
+            line.push(' ');
+            line.push_str(&name);
+        }
+        lines.push(fit(&line, width));
+    }
+    lines
+}
+
+fn title(text: &str, width: usize) -> String {
+    let title = format!("── {} ", text);
+    let length = title.chars().count();
+    fit(&format!("{}{}", title, "─".repeat(width.saturating_sub(length))), width)
+}
+
+// Text cut to the width in characters
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+// Terminal in raw mode showing the alternate screen, restored when dropped
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: the termios structure is only read after tcgetattr filled it
+        let original = unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0 {
+                return Err(io::Error::other("not a terminal"));
+            }
+            let mut original = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original
+        };
+        let mut output = io::stdout().lock();
+        write!(output, "\x1b[?1049h\x1b[?25l")?;
+        output.flush()?;
+        Ok(Self { original })
+    }
+
+    // Columns and rows of the terminal
+    fn size(&self) -> (usize, usize) {
+        // SAFETY: TIOCGWINSZ only writes to the winsize structure
+        let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+        match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+            0 if size.ws_col > 0 && size.ws_row > 0 => (size.ws_col as usize, size.ws_row as usize),
+            _ => (80, 24),
+        }
+    }
+
+    fn draw(&self, lines: &[String]) -> io::Result<()> {
+        let mut output = io::stdout().lock();
+        write!(output, "\x1b[H")?;
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                write!(output, "\r\n")?;
+            }
+            write!(output, "{}\x1b[0m\x1b[K", line)?;
+        }
+        write!(output, "\x1b[J")?;
+        output.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes read by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+        let mut output = io::stdout().lock();
+        write!(output, "\x1b[?25h\x1b[?1049l").ok();
+        output.flush().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_keys(b"\x1b[A\x1b[Bjkrm"), [Key::Up, Key::Down, Key::Down, Key::Up, Key::Ping, Key::Mute]);
+        assert_eq!(parse_keys(b"\x1b[5~\x1b[6~"), [Key::PageUp, Key::PageDown]);
+        assert_eq!(parse_keys(b"\x1b[C"), []);
+        assert_eq!(parse_keys(b"\x1b"), [Key::Quit]);
+        assert_eq!(parse_keys(b"\x03"), [Key::Quit]);
+    }
+}
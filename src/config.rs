@@ -0,0 +1,217 @@
+use std::{error::Error, fs, time::Duration};
+use serde::{Deserialize, Serialize};
+use serde_json::{map::Map, Value};
+
+use crate::{
+    models::InternalError,
+    notify::{EventKind, OutboxConfig, Rule},
+    server::ServerConfig,
+    history::HistoryConfig,
+    reports::ReportConfig,
+    badge::BadgeConfig,
+    page::PageConfig,
+};
+
+pub const CONFIG_FILE: &str = "mc-ping.json";
+pub const CONFIG_ENV: &str = "MC_PING_CONFIG";
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    // Default time (in seconds) between pings. Defaults to 1
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+
+    // Ping and notification request timeouts
+    #[serde(default)]
+    pub timeout: Timeouts,
+
+    // Log level or filters in the RUST_LOG format. The RUST_LOG environment variable takes precedence
+    pub log_level: Option<String>,
+
+    // Servers to monitor. Every target is polled in its own task
+    #[serde(default)]
+    pub targets: Vec<Target>,
+
+    // Notifier instances. Without this list every compiled notifier type reads its own ./<type>.json file
+    pub notifiers: Option<Vec<NotifierConfig>>,
+
+    // Embedded HTTP server. Disabled when not set
+    pub http: Option<ServerConfig>,
+
+    // Durable queue of the notification requests, replayed after failures and restarts. Disabled when not set
+    pub outbox: Option<OutboxConfig>,
+
+    // Rules routing events to notifier instances. Without any rules every event is sent to every notifier
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+
+    // Persistent status history. Disabled when not set
+    pub history: Option<HistoryConfig>,
+
+    // Summaries of the history sent on a schedule. Require the history
+    #[serde(default)]
+    pub reports: Vec<ReportConfig>,
+
+    // Status badges served by the HTTP server and written to disk
+    #[serde(default)]
+    pub badge: BadgeConfig,
+
+    // Static HTML status page. Disabled when not set
+    pub page: Option<PageConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: default_interval(),
+            timeout: Timeouts::default(),
+            log_level: None,
+            targets: Vec::new(),
+            notifiers: None,
+            outbox: None,
+            http: None,
+            rules: Vec::new(),
+            history: None,
+            reports: Vec::new(),
+            badge: BadgeConfig::default(),
+            page: None,
+        }
+    }
+}
+
+impl Config {
+    // Fills in the values targets inherit from the main configuration
+    pub fn prepare(&mut self) -> Result<(), InternalError> {
+        if self.targets.is_empty() {
+            return Err(InternalError::new("no targets defined"));
+        }
+        // Without a pause the targets would be pinged as fast as they respond
+        if self.interval == 0 {
+            return Err(InternalError::new("the interval must be at least 1 second"));
+        }
+        if self.timeout.ping == 0 {
+            return Err(InternalError::new("the ping timeout must be at least 1 second"));
+        }
+        for target in self.targets.iter_mut() {
+            if target.name.is_empty() {
+                target.name = target.host.clone();
+            }
+            if target.interval == Some(0) {
+                return Err(InternalError::new(format!("the interval of target '{}' must be at least 1 second", target.name)));
+            }
+            target.interval.get_or_insert(self.interval);
+        }
+        // Targets are told apart by their names
+        for (index, target) in self.targets.iter().enumerate() {
+            if self.targets[..index].iter().any(|other| other.name == target.name) {
+                return Err(InternalError::new(format!(
+                    "target name '{}' is used more than once. Names default to the host, so set a different \"name\" for targets on the same host",
+                    target.name,
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Timeouts {
+    // Time (in seconds) after which a ping is considered failed. Defaults to 5
+    #[serde(default = "default_timeout")]
+    pub ping: u64,
+
+    // Time (in seconds) after which a notification request is considered failed. Defaults to 5
+    #[serde(default = "default_timeout")]
+    pub request: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self { ping: default_timeout(), request: default_timeout() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotifierConfig {
+    // Unique name of the notifier instance. Defaults to the type
+    pub name: Option<String>,
+
+    // Type of the notifier: "firebase", "discord", "slack" or "custom"
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    // Events the instance is notified about. Defaults to all events
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+
+    // Type specific settings, the same as in the ./<type>.json files
+    #[serde(flatten)]
+    pub settings: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Target {
+    // Name of the target used in logs and the "%name" placeholder. Defaults to the hostname. Must be unique
+    #[serde(default)]
+    pub name: String,
+
+    // Hostname or IP address of the server
+    pub host: String,
+
+    // Port of the server. Defaults to 25565 for Java and 19132 for Bedrock
+    pub port: Option<u16>,
+
+    // Minecraft edition of the server. Defaults to "java"
+    #[serde(default)]
+    pub edition: Edition,
+
+    // Time (in seconds) between pings. Defaults to the main configuration interval
+    pub interval: Option<u64>,
+
+    // Free-form labels describing the target
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Target {
+    pub fn new(host: String, port: Option<u16>) -> Self {
+        Self {
+            name: host.clone(),
+            host,
+            port,
+            ..Default::default()
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.edition {
+            Edition::Java => 25565,
+            Edition::Bedrock => 19132,
+        })
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval.unwrap_or_else(default_interval))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edition {
+    #[default]
+    Java,
+    Bedrock,
+}
+
+fn default_interval() -> u64 {
+    1
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+pub fn read(filename: &str) -> Result<Config, Box<dyn Error>> {
+    let file = fs::read(filename).map_err(|err| InternalError::new(format!("unable to read {} file: {}", filename, err)))?;
+    Ok(serde_json::from_slice::<Config>(&file)?)
+}
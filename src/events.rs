@@ -0,0 +1,100 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use async_std::channel::{self, Receiver, Sender};
+use once_cell::sync::Lazy;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    models::Status,
+    notify::{Event, EventKind},
+};
+
+// Number of events kept for Last-Event-ID replays
+const HISTORY: usize = 256;
+
+// Number of frames buffered for a subscriber before it's considered too slow and dropped
+const BUFFER: usize = 64;
+
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub id: u64,
+    pub event: EventKind,
+    pub target: String,
+    // Unix time of the event
+    pub time: u64,
+    #[serde(serialize_with = "without_favicon")]
+    pub status: Arc<Status>,
+}
+
+struct Bus {
+    next_id: u64,
+    history: VecDeque<Arc<Record>>,
+    subscribers: Vec<Sender<Arc<str>>>,
+}
+
+// IDs start at the Unix time in milliseconds the process started at, so they keep growing across restarts
+// and a Last-Event-ID from before a restart doesn't match the events after it
+static BUS: Lazy<Mutex<Bus>> = Lazy::new(|| {
+    let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or_default();
+    Mutex::new(Bus { next_id: epoch, history: VecDeque::new(), subscribers: Vec::new() })
+});
+
+// The favicon is left out of the status, as it would be sent with every event
+fn without_favicon<S: Serializer>(status: &Arc<Status>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut value = serde_json::to_value(status.as_ref()).map_err(serde::ser::Error::custom)?;
+    if let Some(status) = value.as_object_mut() {
+        status.remove("favicon");
+    }
+    value.serialize(serializer)
+}
+
+pub fn publish(event: &Event) {
+    let mut bus = BUS.lock().unwrap();
+    bus.next_id += 1;
+    let record = Arc::new(Record {
+        id: bus.next_id,
+        event: event.kind,
+        target: event.status.target.name.clone(),
+        time: event.time,
+        status: event.status.clone(),
+    });
+    let frame: Arc<str> = frame(&record).into();
+    bus.subscribers.retain(|subscriber| subscriber.try_send(frame.clone()).is_ok());
+    if bus.history.len() == HISTORY {
+        bus.history.pop_front();
+    }
+    bus.history.push_back(record);
+}
+
+// Subscribes to the Server-Sent Events frames, starting with the events after last_id
+pub fn subscribe(last_id: Option<u64>) -> Receiver<Arc<str>> {
+    let mut bus = BUS.lock().unwrap();
+    let replay = match last_id {
+        Some(last_id) => bus.history.iter().filter(|record| record.id > last_id).cloned().collect(),
+        None => Vec::new(),
+    };
+    let (sender, receiver) = channel::bounded(BUFFER.max(replay.len() + 1));
+    for record in replay {
+        sender.try_send(frame(&record).into()).ok();
+    }
+    bus.subscribers.push(sender);
+    receiver
+}
+
+// Sends a comment to every subscriber, dropping the ones that disconnected
+pub fn heartbeat() {
+    let frame: Arc<str> = ":\n\n".into();
+    BUS.lock().unwrap().subscribers.retain(|subscriber| subscriber.try_send(frame.clone()).is_ok());
+}
+
+pub fn recent() -> Vec<Arc<Record>> {
+    BUS.lock().unwrap().history.iter().cloned().collect()
+}
+
+fn frame(record: &Record) -> String {
+    let data = serde_json::to_string(record).unwrap_or_default();
+    format!("id: {}\nevent: {}\ndata: {}\n\n", record.id, record.event.name(), data)
+}
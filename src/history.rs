@@ -0,0 +1,405 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use async_std::task;
+use once_cell::sync::{Lazy, OnceCell};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Target,
+    models::{InternalError, Status},
+    notify::{Event, EventKind},
+    state,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryConfig {
+    // Directory the history files are stored in. Defaults to "history"
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    // Days after which samples and events are removed. Kept forever when null
+    #[serde(default = "default_retention")]
+    pub retention: Option<u64>,
+
+    // Days after which samples are downsampled
+    #[serde(default = "default_downsample_after")]
+    pub downsample_after: u64,
+
+    // Seconds covered by a single downsampled sample
+    #[serde(default = "default_downsample_resolution")]
+    pub downsample_resolution: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            path: default_path(),
+            retention: default_retention(),
+            downsample_after: default_downsample_after(),
+            downsample_resolution: default_downsample_resolution(),
+        }
+    }
+}
+
+fn default_path() -> String {
+    "history".to_owned()
+}
+
+fn default_retention() -> Option<u64> {
+    Some(90)
+}
+
+fn default_downsample_after() -> u64 {
+    7
+}
+
+fn default_downsample_resolution() -> u64 {
+    60
+}
+
+// Characters encoded in the file names of the targets
+const FILE_NAME: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+// Time between compactions of the history files
+const COMPACT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    // Unix time of the ping, or the start of the period a downsampled sample covers
+    pub time: u64,
+    // Number of pings the sample covers
+    pub count: u32,
+    // Share of the pings that succeeded
+    pub uptime: f64,
+    // Average number of online players during the successful pings
+    pub online: f64,
+    // Highest number of online players
+    pub peak: u32,
+    // Time at which the number of online players peaked
+    pub peak_time: u64,
+    // Highest maximum number of players
+    pub max: u32,
+    // Average latency of the successful pings in milliseconds
+    pub latency: f64,
+    // Names of the players seen in the samples of the player list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub players: Vec<String>,
+}
+
+impl Sample {
+    fn up(time: u64, status: &Status) -> Self {
+        Self {
+            time,
+            count: 1,
+            uptime: 1.0,
+            online: status.players.online as f64,
+            peak: status.players.online,
+            peak_time: time,
+            max: status.players.max,
+            latency: status.latency as f64,
+            players: status.players.sample.iter().flatten().map(|player| player.name.clone()).collect(),
+        }
+    }
+
+    fn down(time: u64) -> Self {
+        Self { time, count: 1, uptime: 0.0, online: 0.0, peak: 0, peak_time: time, max: 0, latency: 0.0, players: Vec::new() }
+    }
+
+    fn successes(&self) -> f64 {
+        self.uptime * self.count as f64
+    }
+
+    // Combines another sample into this one, weighting the averages by the number of pings
+    fn merge(&mut self, other: &Sample) {
+        let (successes, other_successes) = (self.successes(), other.successes());
+        if successes + other_successes > 0.0 {
+            self.online = (self.online * successes + other.online * other_successes) / (successes + other_successes);
+            self.latency = (self.latency * successes + other.latency * other_successes) / (successes + other_successes);
+        }
+        let count = self.count + other.count;
+        self.uptime = (successes + other_successes) / count as f64;
+        self.count = count;
+        if other.peak > self.peak {
+            self.peak = other.peak;
+            self.peak_time = other.peak_time;
+        }
+        self.max = self.max.max(other.max);
+        for player in other.players.iter() {
+            if !self.players.contains(player) {
+                self.players.push(player.clone());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    // Unix time of the event
+    pub time: u64,
+    pub event: EventKind,
+    // Number of online players at the time of the event
+    #[serde(default)]
+    pub online: u32,
+    #[serde(default)]
+    pub max: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Sample(Sample),
+    Event(EventRecord),
+}
+
+impl Entry {
+    fn time(&self) -> u64 {
+        match self {
+            Entry::Sample(sample) => sample.time,
+            Entry::Event(event) => event.time,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    // Number of pings
+    pub pings: u64,
+    // Percentage of the pings that succeeded
+    pub uptime_percent: f64,
+    // Average number of online players
+    pub avg_online: f64,
+    // Highest number of online players
+    pub peak: u32,
+    // Time at which the number of online players peaked
+    pub peak_time: Option<u64>,
+    // Number of different players seen in the samples of the player list
+    pub unique_players: usize,
+}
+
+impl Summary {
+    pub fn of(samples: &[Sample]) -> Self {
+        let mut total = match samples.first() {
+            Some(first) => first.clone(),
+            None => return Self::default(),
+        };
+        for sample in &samples[1..] {
+            total.merge(sample);
+        }
+        Self {
+            pings: samples.iter().map(|sample| sample.count as u64).sum(),
+            uptime_percent: total.uptime * 100.0,
+            avg_online: total.online,
+            peak: total.peak,
+            peak_time: Some(total.peak_time),
+            unique_players: total.players.len(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    pub summary: Summary,
+    pub samples: Vec<Sample>,
+    pub events: Vec<EventRecord>,
+}
+
+static CONFIG: OnceCell<HistoryConfig> = OnceCell::new();
+
+// History file of every target, keyed by the target name. Each is locked on its own while it is written or compacted,
+// and only kept open for appending once the target recorded something
+static FILES: Lazy<Mutex<HashMap<String, HistoryFile>>> = Lazy::new(Default::default);
+
+type HistoryFile = Arc<Mutex<Option<File>>>;
+
+fn lock(target: &str) -> HistoryFile {
+    FILES.lock().unwrap().entry(target.to_owned()).or_default().clone()
+}
+
+// Starts recording the history and compacts the history files of the targets in the background
+pub fn init(config: &HistoryConfig, targets: &[Target]) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&config.path)?;
+    CONFIG.set(config.clone()).ok();
+    let targets = targets.iter().map(|target| target.name.clone()).collect::<Vec<_>>();
+    let config = CONFIG.get().unwrap();
+    task::spawn(async move {
+        loop {
+            for target in targets.iter().cloned() {
+                // The file is read and written again on a blocking thread, holding up only the records of that target
+                let result = task::spawn_blocking(move || compact(config, &target).map_err(|err| (target, err.to_string()))).await;
+                if let Err((target, err)) = result {
+                    log::error!("failed to compact the history of {}: {}", target, err);
+                }
+            }
+            task::sleep(COMPACT_INTERVAL).await;
+        }
+    });
+    Ok(())
+}
+
+// Configuration of the history recorded by the monitor, if enabled
+pub fn config() -> Option<&'static HistoryConfig> {
+    CONFIG.get()
+}
+
+pub async fn record_status(status: &Status) {
+    record(&status.target.name, Entry::Sample(Sample::up(state::now(), status))).await;
+}
+
+pub async fn record_error(target: &str) {
+    record(target, Entry::Sample(Sample::down(state::now()))).await;
+}
+
+// Records the event in the background, as the events are sent from outside of the monitors
+pub fn record_event(event: &Event) {
+    let target = event.status.target.name.clone();
+    let entry = Entry::Event(EventRecord {
+        time: state::now(),
+        event: event.kind,
+        online: event.status.players.online,
+        max: event.status.players.max,
+    });
+    task::spawn(async move { record(&target, entry).await });
+}
+
+// Appends the entry on a blocking thread, waiting for a compaction of the file of the target to finish
+async fn record(target: &str, entry: Entry) {
+    let config = match CONFIG.get() {
+        Some(config) => config,
+        None => return,
+    };
+    let file = lock(target);
+    let path = file_path(config, target);
+    let result = task::spawn_blocking(move || {
+        let mut file = file.lock().unwrap();
+        if file.is_none() {
+            *file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        file.as_mut().unwrap().write_all(&line)?;
+        Ok::<_, std::io::Error>(())
+    }).await;
+    if let Err(err) = result {
+        log::error!("failed to record the history of {}: {}", target, err);
+    }
+}
+
+// Samples and events of a target between two Unix times, optionally merged into periods of the resolution in seconds
+pub fn query(config: &HistoryConfig, target: &str, from: u64, to: u64, resolution: Option<u64>) -> Result<History, Box<dyn Error>> {
+    let resolution = resolution.filter(|resolution| *resolution > 1);
+    let mut samples = Vec::new();
+    let mut periods = BTreeMap::new();
+    let mut events = Vec::new();
+    // The samples are merged while reading, so only the periods are kept
+    read(config, target, from, to, |entry| match (entry, resolution) {
+        (Entry::Sample(sample), Some(resolution)) => merge_period(&mut periods, sample, resolution),
+        (Entry::Sample(sample), None) => samples.push(sample),
+        (Entry::Event(event), _) => events.push(event),
+    })?;
+    if resolution.is_some() {
+        samples = periods.into_values().collect();
+    }
+    Ok(History { summary: Summary::of(&samples), samples, events })
+}
+
+// Queries the history on a blocking thread, for the callers running on the async tasks
+pub async fn load(config: &'static HistoryConfig, target: &str, from: u64, to: u64, resolution: Option<u64>) -> Result<History, Box<dyn Error>> {
+    let target = target.to_owned();
+    task::spawn_blocking(move || query(config, &target, from, to, resolution).map_err(|err| err.to_string()))
+        .await
+        .map_err(|err| InternalError::new(err).into())
+}
+
+// Whether any history was recorded for the target
+pub fn exists(config: &HistoryConfig, target: &str) -> bool {
+    file_path(config, target).exists()
+}
+
+// Time of an entry, read before the whole entry
+#[derive(Deserialize)]
+struct EntryTime {
+    time: u64,
+}
+
+// Passes the entries between two Unix times to the callback line by line, without parsing the others fully
+fn read<F: FnMut(Entry)>(config: &HistoryConfig, target: &str, from: u64, to: u64, mut callback: F) -> Result<(), Box<dyn Error>> {
+    let file = match File::open(file_path(config, target)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if serde_json::from_str::<EntryTime>(&line).is_ok_and(|entry| entry.time < from || entry.time > to) {
+            continue;
+        }
+        // A line may be cut short when the program is stopped while writing it
+        match serde_json::from_str(&line) {
+            Ok(entry) => callback(entry),
+            Err(err) if !line.trim().is_empty() => log::debug!("skipping invalid history entry of {}: {}", target, err),
+            Err(_) => {},
+        }
+    }
+    Ok(())
+}
+
+// Merges the sample into the period of the resolution it falls in
+fn merge_period(periods: &mut BTreeMap<u64, Sample>, mut sample: Sample, resolution: u64) {
+    let start = sample.time / resolution * resolution;
+    match periods.get_mut(&start) {
+        Some(period) => period.merge(&sample),
+        None => {
+            sample.time = start;
+            periods.insert(start, sample);
+        },
+    }
+}
+
+// Removes the entries past the retention and downsamples the old samples
+fn compact(config: &HistoryConfig, target: &str) -> Result<(), Box<dyn Error>> {
+    let file = lock(target);
+    let mut file = file.lock().unwrap();
+    let now = state::now();
+    let expired = config.retention.map(|days| now.saturating_sub(days * 86400)).unwrap_or(0);
+    let old = now.saturating_sub(config.downsample_after * 86400);
+    let mut old_periods = BTreeMap::new();
+    let mut entries = Vec::new();
+    read(config, target, expired, u64::MAX, |entry| match entry {
+        Entry::Sample(sample) if sample.time < old => merge_period(&mut old_periods, sample, config.downsample_resolution.max(1)),
+        entry => entries.push(entry),
+    })?;
+    entries.extend(old_periods.into_values().map(Entry::Sample));
+    entries.sort_by_key(Entry::time);
+
+    // The compacted entries replace the file at once so a failure leaves the previous file intact
+    let path = file_path(config, target);
+    let temporary = path.with_extension("jsonl.tmp");
+    let mut output = File::create(&temporary)?;
+    for entry in entries.iter() {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        output.write_all(&line)?;
+    }
+    output.sync_all()?;
+    fs::rename(&temporary, &path)?;
+    // The next record opens the new file
+    *file = None;
+    Ok(())
+}
+
+fn file_path(config: &HistoryConfig, target: &str) -> PathBuf {
+    PathBuf::from(&config.path).join(format!("{}.jsonl", file_name(target)))
+}
+
+// Target name usable as a file name. Other characters are percent-encoded, so every name maps to a different file
+pub fn file_name(target: &str) -> String {
+    utf8_percent_encode(target, FILE_NAME).to_string()
+}
@@ -2,195 +2,282 @@ use std::{
     process::ExitCode,
     time::Duration,
     error::Error,
-    borrow::Cow,
+    path::Path,
+    sync::Arc,
 };
-use async_std::{
-    net::{
-        TcpStream,
-        Shutdown,
-    },
-    io::{
-        WriteExt,
-        ReadExt,
-    },
-    task,
-};
-use resolv::{
-    record::SRV,
-    RecordType,
-    Resolver,
-    Class,
-};
-use once_cell::sync::OnceCell;
+use async_std::task;
+use futures_util::future::join_all;
 
 mod notify;
 mod models;
+mod config;
+mod ping;
+mod cli;
+mod commands;
+mod metrics;
+mod server;
+mod state;
+mod events;
+mod history;
+mod time;
+mod reports;
+mod chart;
+mod badge;
+mod motd;
+mod page;
+mod template;
 use models::{
     InternalError,
     Status,
 };
-
-static HOSTNAME: OnceCell<String> = OnceCell::new();
+use notify::{EventKind, Timeline};
+use cli::{Args, Command};
+use config::{
+    Config,
+    Target,
+};
+use ping::{Address, ErrorKind};
 
 #[async_std::main]
 async fn main() -> Result<ExitCode, Box<dyn Error>> {
-    let mut args = std::env::args();
-    if args.len() < 2 {
-        println!("Usage: {} <hostname> [port]", args.next().unwrap());
+    let args = match Args::parse(std::env::args()) {
+        Ok(args) => args,
+        Err(err) => {
+            println!("Error: {}", err);
+            return Ok(ExitCode::FAILURE)
+        }
+    };
+    if args.help {
+        println!("{}", args.usage());
         return Ok(ExitCode::SUCCESS)
     }
-    args.next();
-    HOSTNAME.set(args.next().unwrap())?;
-    let mut host = Cow::from(HOSTNAME.get().unwrap());
-    let mut port = 25565_u16;
-    if let Some(raw) = args.next() {
-        match raw.parse::<u16>() {
-            Ok(p) => port = p,
+    match args.command {
+        Command::Status => {
+            init_logger(None, &args);
+            return Ok(commands::status::run(&args).await)
+        },
+        Command::Check => {
+            init_logger(None, &args);
+            return Ok(commands::check::run(&args).await)
+        },
+        Command::History | Command::Export => {
+            init_logger(None, &args);
+            let config = match config_path(&args).map(|path| config::read(&path)).transpose() {
+                Ok(config) => config.and_then(|config| config.history).unwrap_or_default(),
+                Err(err) => {
+                    println!("Error: {}", err);
+                    return Ok(ExitCode::FAILURE)
+                }
+            };
+            return Ok(match args.command {
+                Command::Export => commands::export::run(&args, &config),
+                _ => commands::history::run(&args, &config),
+            })
+        },
+        Command::Monitor | Command::Tui => {},
+    }
+
+    let path = config_path(&args);
+    let mut config = match path.as_ref() {
+        Some(path) => match config::read(path) {
+            Ok(config) => config,
             Err(err) => {
-                println!("Error: '{}' is not a valid port number: {}", raw, err);
+                println!("Error: {}", err);
                 return Ok(ExitCode::FAILURE)
             }
+        },
+        None if args.hostname.is_none() => {
+            println!("{}", args.usage());
+            return Ok(ExitCode::SUCCESS)
+        },
+        None => Config::default(),
+    };
+
+    if let Some(hostname) = args.hostname.clone() {
+        config.targets = vec![Target::new(hostname, args.port)];
+    }
+    if let Some(interval) = args.interval {
+        config.interval = interval;
+        for target in config.targets.iter_mut() {
+            target.interval = None;
         }
     }
+    if let Some(timeout) = args.ping_timeout {
+        config.timeout.ping = timeout;
+    }
+    if let Some(timeout) = args.request_timeout {
+        config.timeout.request = timeout;
+    }
+    if let Err(err) = config.prepare() {
+        println!("Error: {}", err);
+        return Ok(ExitCode::FAILURE)
+    }
 
-    pretty_env_logger::init();
+    // Logs would be drawn over the terminal UI
+    if args.command != Command::Tui {
+        init_logger(config.log_level.as_ref(), &args);
+    }
 
-    if let Err(err) = notify::init().await {
+    if let Err(err) = notify::init(&config).await {
         log::error!("failed to initialize: {}", err);
         return Ok(ExitCode::FAILURE)
     }
-    
-    let dname = format!("_minecraft._tcp.{}", host);
-    if let Ok(mut res) = Resolver::new().unwrap().query(dname.as_bytes(), Class::IN, RecordType::SRV) {
-        if let Some(record) = res.answers::<SRV>().next() {
-            log::info!("SRV record found: {}:{} -> {}:{}", host, port, record.data.name, record.data.port);
-            *host.to_mut() = record.data.name;
-            port = record.data.port;
+    if let Some(history) = config.history.as_ref() {
+        if let Err(err) = history::init(history, &config.targets) {
+            log::error!("failed to open the history in {}: {}", history.path, err);
+            return Ok(ExitCode::FAILURE)
+        }
+        if let Err(err) = reports::start(config.reports.clone(), history, &config.targets) {
+            log::error!("failed to schedule the reports: {}", err);
+            return Ok(ExitCode::FAILURE)
+        }
+    } else if !config.reports.is_empty() {
+        log::error!("reports require the history to be enabled");
+        return Ok(ExitCode::FAILURE)
+    }
+    if let Err(err) = badge::init(&config.badge) {
+        log::error!("failed to create the badge directory: {}", err);
+        return Ok(ExitCode::FAILURE)
+    }
+    if let Some(page) = config.page.as_ref() {
+        if let Err(err) = page::init(page) {
+            log::error!("failed to set up the status page: {}", err);
+            return Ok(ExitCode::FAILURE)
         }
     }
+    if let Some(server) = config.http.as_ref() {
+        if let Err(err) = server::start(server).await {
+            log::error!("failed to start the HTTP server on {}: {}", server.listen, err);
+            return Ok(ExitCode::FAILURE)
+        }
+    }
+
+    let timeout = Duration::from_secs(config.timeout.ping);
+    let tasks = config.targets.into_iter()
+        .map(|target| task::spawn(monitor(Arc::new(target), timeout)))
+        .collect::<Vec<_>>();
+    if args.command == Command::Tui {
+        return Ok(commands::tui::run().await)
+    }
+    join_all(tasks).await;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn config_path(args: &Args) -> Option<String> {
+    args.config.clone()
+        .or_else(|| std::env::var(config::CONFIG_ENV).ok())
+        .or_else(|| Path::new(config::CONFIG_FILE).exists().then(|| config::CONFIG_FILE.to_owned()))
+}
+
+// Log filters from the configuration, RUST_LOG and the command line, in increasing precedence
+fn init_logger(config: Option<&String>, args: &Args) {
+    let mut logger = pretty_env_logger::formatted_builder();
+    if let Some(filters) = config {
+        logger.parse_filters(filters);
+    }
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        logger.parse_filters(&filters);
+    }
+    if let Some(filters) = args.log_level.as_ref() {
+        logger.parse_filters(filters);
+    }
+    logger.init();
+}
+
+async fn monitor(target: Arc<Target>, timeout: Duration) {
+    let address = Address::resolve(target.clone());
+    let (host, port) = (&address.host, address.port);
+    let ping_requests = state::register(&target, host, port);
+    log::info!("Monitoring {} ({}:{}) tags: [{}]", target.name, host, port, target.tags.join(", "));
 
-    let handshake = handshake(&host, &port);
     let mut last = 0;
+    // State given to the events as the one before them
+    let mut timeline = Timeline::default();
+    let mut motd: Option<String> = None;
+    let mut down = false;
     let mut fail: u8 = 0;
     let mut forge_data_fail: u8 = 0;
     loop {
-        match ping(&handshake, &host, &port).await {
-            Ok(mut status) => {
+        match address.ping(timeout).await {
+            Ok(status) => {
                 forge_data_fail = 0;
                 fail = 0;
-                if last != status.players.online {
+                metrics::record_status(&status);
+                history::record_status(&status).await;
+                let status = Arc::new(status);
+                state::record_status(status.clone());
+                badge::update(&status, true);
+                let went_up = down;
+                let first = motd.is_none();
+                let changed = last != status.players.online;
+                // No MOTD event for the first status received
+                let motd_changed = motd.as_ref().is_some_and(|motd| *motd != status.description.text);
+                motd = Some(status.description.text.clone());
+                if went_up {
+                    down = false;
+                    log::info!("{} ({}:{}) is up again", target.name, host, port);
+                }
+                if changed {
                     last = status.players.online;
-                    log::info!("Status for {}:{}: {} {}/{}", host, port, status.description.text, status.players.online, status.players.max);
-                    status.host = host.clone();
-                    status.port = port;
-                    notify::notify(status);
+                    log::info!("Status for {} ({}:{}): {} {}/{}", target.name, host, port, status.description.text, status.players.online, status.players.max);
+                }
+                if first || went_up || changed || motd_changed {
+                    page::update();
+                }
+                if timeline.up_since.is_none() {
+                    timeline.up_since = Some(state::now());
+                }
+                if went_up {
+                    notify::notify(EventKind::WentUp, status.clone(), timeline.clone());
+                    timeline.down_since = None;
+                }
+                if motd_changed {
+                    notify::notify(EventKind::MotdChanged, status.clone(), timeline.clone());
                 }
+                if changed {
+                    notify::notify(EventKind::PlayersChanged, status.clone(), timeline.clone());
+                }
+                if went_up || motd_changed || changed {
+                    timeline.last_change = Some(state::now());
+                }
+                timeline.previous = Some(status);
             },
-            Err(mut err) => {
+            Err(mut err) => 'failed: {
                 if err.to_string().starts_with("control character (\\u0000-\\u001F)") {
                     forge_data_fail += 1;
+                    // Forge data is sometimes cut short, so it only counts as a failure once it keeps failing
                     if forge_data_fail < 10 {
-                        continue;
+                        log::debug!("Failed to parse the forge data of {}, retrying", target.name);
+                        break 'failed;
                     }
                     err = Box::new(InternalError::new("forge data control character parse failure"));
                     forge_data_fail = 0;
                 }
-                fail += 1;
+                metrics::record_error(&target.name, ErrorKind::of(err.as_ref()));
+                state::record_error(&target.name, err.to_string());
+                history::record_error(&target.name).await;
+                fail = fail.saturating_add(1);
                 if fail == 10 {
-                    panic!("Failed to request status 10 times! Error: {}", err);
+                    log::error!("Failed to request status of {} 10 times! Marking as down. Error: {}", target.name, err);
+                    down = true;
+                    metrics::record_down(&target.name);
+                    let status = Arc::new(Status::offline(host.clone(), port, target.clone()));
+                    badge::update(&status, false);
+                    page::update();
+                    timeline.down_since = state::get(&target.name).and_then(|state| state.last_success);
+                    notify::notify(EventKind::WentDown, status.clone(), timeline.clone());
+                    timeline = Timeline {
+                        previous: Some(status),
+                        last_change: Some(state::now()),
+                        up_since: None,
+                        down_since: timeline.down_since,
+                    };
+                } else if !down {
+                    log::error!("Failed to request status of {}: {}", target.name, err);
                 }
-                log::error!("Failed to request status: {}", err);
             }
         }
-        task::sleep(Duration::from_secs(1)).await;
-    }
-}
-
-async fn ping(handshake: &[u8], host: &str, port: &u16) -> Result<Status, Box<dyn Error>> {
-    log::debug!("connecting to: {}:{}", host, port);
-    let mut stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
-
-    log::debug!("writing handshake {:?}", handshake);
-    stream.write_all(handshake).await?;
-
-    let status = request_status(&mut stream).await?;
-
-    stream.write(&Vec::<u8>::new()).await?;
-    stream.shutdown(Shutdown::Both)?;
-    Ok(status)
-}
-
-const REQUEST: [u8; 2] = [1, 0];
-
-async fn request_status(stream: &mut TcpStream) -> Result<Status, Box<dyn Error>> {
-    log::debug!("writing request");
-    stream.write_all(&REQUEST).await?;
-    log::debug!("reading length");
-    let length = from_var_int(stream).await?;
-    log::debug!("length {}", length);
-    if length > 0 {
-        let prefix = from_var_int(stream).await?;
-        log::debug!("string prefix: {}", prefix);
-
-        let string_length = from_var_int(stream).await?;
-        log::debug!("string length: {}", string_length);
-
-        let mut buf = vec![0u8; string_length as usize];
-        stream.read(&mut buf).await?;
-        log::debug!("read status ({} bytes)\n{}", string_length, String::from_utf8_lossy(&buf));
-        Ok(serde_json::from_slice::<Status>(&buf)?)
-    } else {
-        Err(InternalError::new("invalid status length").into())
-    }
-}
-
-fn handshake(host: &str, port: &u16) -> Vec<u8> {
-    let host = host.to_owned() + "\0FML3\0";
-    let mut data = to_var_int(-1); // Protocol Number
-    data.extend(to_var_int(host.len() as i32)); // Host length
-    data.extend(host.bytes()); // Host
-    data.push((port & 0x00FF) as u8); // Port lower
-    data.push((port >> 8) as u8); // Port upper
-    data.push(1); // Next state
-    let mut handshake = to_var_int(data.len() as i32 + 1); // Packet length
-    handshake.push(0); // Packet ID
-    handshake.extend(data);
-    handshake
-}
-
-const SEGMENT: u32 = 0x7F;
-const CONTINUE: u32 = 0x80;
-
-fn to_var_int(input: i32) -> Vec<u8> {
-    let mut input = input as u32;
-    let mut data = Vec::<u8>::new();
-    loop {
-        if input & !SEGMENT == 0 {
-            data.push(input as u8);
-            break;
-        }
-        data.push(((input & SEGMENT) | CONTINUE) as u8);
-        input >>= 7;
-    }
-    data
-}
-
-async fn from_var_int(input: &mut TcpStream) -> Result<i32, Box<dyn Error>> {
-    let mut result = 0;
-    let mut i = 0;
-    loop {
-        let mut buf = vec![0u8; 1];
-        if input.read(&mut buf).await? == 1 {
-            let byte = buf[0];
-            result |= (((byte as u32) & SEGMENT) as i32) << i;
-            if byte & (CONTINUE as u8) == 0 {
-                break
-            }
-        }
-        i += 7;
-        if i >= 32 {
-            return Err(InternalError::new("varint too long").into());
-        }
+        // Pings again after the interval, or right away when requested
+        async_std::future::timeout(target.interval(), ping_requests.recv()).await.ok();
     }
-    Ok(result as i32)
 }
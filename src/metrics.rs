@@ -0,0 +1,103 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+};
+use once_cell::sync::Lazy;
+
+use crate::{
+    models::Status,
+    ping::ErrorKind,
+    state,
+};
+
+#[derive(Debug, Default)]
+struct TargetMetrics {
+    up: bool,
+    online: u32,
+    max: u32,
+    protocol: u16,
+    latency: u64,
+    last_success: Option<u64>,
+    errors: BTreeMap<ErrorKind, u64>,
+}
+
+static TARGETS: Lazy<Mutex<BTreeMap<String, TargetMetrics>>> = Lazy::new(Default::default);
+
+// Notification deliveries keyed by the notifier name and whether they succeeded
+static NOTIFICATIONS: Lazy<Mutex<BTreeMap<(String, bool), u64>>> = Lazy::new(Default::default);
+
+// Requests in the outboxes keyed by the notifier name
+static OUTBOXES: Lazy<Mutex<BTreeMap<String, usize>>> = Lazy::new(Default::default);
+
+pub fn record_status(status: &Status) {
+    let mut targets = TARGETS.lock().unwrap();
+    let metrics = targets.entry(status.target.name.clone()).or_default();
+    metrics.up = true;
+    metrics.online = status.players.online;
+    metrics.max = status.players.max;
+    metrics.protocol = status.version.protocol;
+    metrics.latency = status.latency;
+    metrics.last_success = Some(state::now());
+}
+
+pub fn record_error(target: &str, kind: ErrorKind) {
+    let mut targets = TARGETS.lock().unwrap();
+    *targets.entry(target.to_owned()).or_default().errors.entry(kind).or_default() += 1;
+}
+
+// The target is only down once the monitor marks it down, like for the notifications
+pub fn record_down(target: &str) {
+    TARGETS.lock().unwrap().entry(target.to_owned()).or_default().up = false;
+}
+
+pub fn record_notification(notifier: &str, delivered: bool) {
+    *NOTIFICATIONS.lock().unwrap().entry((notifier.to_owned(), delivered)).or_default() += 1;
+}
+
+pub fn record_outbox(notifier: &str, depth: usize) {
+    OUTBOXES.lock().unwrap().insert(notifier.to_owned(), depth);
+}
+
+// Renders the metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    let targets = TARGETS.lock().unwrap();
+    let mut output = String::new();
+    let mut gauge = |name: &str, help: &str, value: &dyn Fn(&TargetMetrics) -> Option<String>| {
+        writeln!(output, "# HELP {} {}\n# TYPE {} gauge", name, help, name).unwrap();
+        for (target, metrics) in targets.iter() {
+            if let Some(value) = value(metrics) {
+                writeln!(output, "{}{{target=\"{}\"}} {}", name, escape(target), value).unwrap();
+            }
+        }
+    };
+    gauge("mc_ping_up", "Whether the target is up (fewer than 10 failed pings in a row)", &|metrics| Some((metrics.up as u8).to_string()));
+    gauge("mc_ping_players_online", "Number of online players", &|metrics| Some(metrics.online.to_string()));
+    gauge("mc_ping_players_max", "Maximum number of players", &|metrics| Some(metrics.max.to_string()));
+    gauge("mc_ping_protocol", "Protocol version reported by the server", &|metrics| Some(metrics.protocol.to_string()));
+    gauge("mc_ping_latency_seconds", "Time it took to connect to the server", &|metrics| Some((metrics.latency as f64 / 1000.0).to_string()));
+    gauge("mc_ping_last_success_timestamp_seconds", "Unix time of the last successful ping", &|metrics| metrics.last_success.map(|time| time.to_string()));
+
+    writeln!(output, "# HELP mc_ping_errors_total Failed pings by error kind\n# TYPE mc_ping_errors_total counter").unwrap();
+    for (target, metrics) in targets.iter() {
+        for (kind, count) in metrics.errors.iter() {
+            writeln!(output, "mc_ping_errors_total{{target=\"{}\",kind=\"{}\"}} {}", escape(target), kind, count).unwrap();
+        }
+    }
+
+    writeln!(output, "# HELP mc_ping_notifications_total Notification deliveries by notifier and result\n# TYPE mc_ping_notifications_total counter").unwrap();
+    for ((notifier, delivered), count) in NOTIFICATIONS.lock().unwrap().iter() {
+        let result = if *delivered { "delivered" } else { "failed" };
+        writeln!(output, "mc_ping_notifications_total{{notifier=\"{}\",result=\"{}\"}} {}", escape(notifier), result, count).unwrap();
+    }
+
+    writeln!(output, "# HELP mc_ping_outbox_depth Notification requests waiting in the outbox of the notifier\n# TYPE mc_ping_outbox_depth gauge").unwrap();
+    for (notifier, depth) in OUTBOXES.lock().unwrap().iter() {
+        writeln!(output, "mc_ping_outbox_depth{{notifier=\"{}\"}} {}", escape(notifier), depth).unwrap();
+    }
+    output
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
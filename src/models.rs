@@ -1,5 +1,12 @@
-use std::{error::Error, borrow::Cow};
-use serde::{Deserialize, Serialize};
+use std::{error::Error, sync::Arc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    config::Target,
+    motd,
+    template::{self, Context, Escape, Formatted, Template},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Status {
@@ -14,25 +21,166 @@ pub struct Status {
     // #[serde(rename = "forgeData")]
     // pub forge_data: Option<ForgeData>, // TODO: Implement deserialization
 
-    #[serde(skip)]
-    pub host: Cow<'static, str>,
-    #[serde(skip)]
+    #[serde(skip_deserializing)]
+    pub host: String,
+    #[serde(skip_deserializing)]
     pub port: u16,
+    // Time (in milliseconds) it took to connect to the server
+    #[serde(skip_deserializing)]
+    pub latency: u64,
+    // Time (in milliseconds) it took to receive the status
+    #[serde(skip_deserializing)]
+    pub response_time: u64,
+    #[serde(skip)]
+    pub target: Arc<Target>,
 }
 
 impl Status {
-    pub fn format<S: AsRef<str>, P: AsRef<str>>(&self, input: S, player_separator: P) -> String {
-        input.as_ref().replace("%version", &self.version.name)
-            .replace("%description", &self.description.text)
-            .replace("%online", &self.players.online.to_string())
-            .replace("%max", &self.players.max.to_string())
-            .replace("%players", &self.players.to_string(player_separator))
-            .replace("%hostname", crate::HOSTNAME.get().unwrap())
-            .replace("%host", &self.host)
-            .replace("%port", &self.port.to_string())
+    // Status reported for a server that stopped responding
+    pub fn offline(host: String, port: u16, target: Arc<Target>) -> Self {
+        Self {
+            version: Version { name: String::new(), protocol: 0 },
+            players: Players { max: 0, online: 0, sample: None },
+            description: Description { text: String::new() },
+            favicon: None,
+            enforces_secure_chat: false,
+            previews_chat: false,
+            host,
+            port,
+            latency: 0,
+            response_time: 0,
+            target,
+        }
+    }
+
+    pub fn format<S: AsRef<str>>(&self, input: S, players: &PlayerSettings) -> String {
+        template::render(input.as_ref(), &self.context(players), Escape::None)
+    }
+
+    // Variables of the templates, also read by the "%name" placeholders
+    pub fn context(&self, settings: &PlayerSettings) -> Context {
+        let mut context = Context::new(&settings.players_separator);
+        let mut sample = self.players.sample.iter().flatten().collect::<Vec<_>>();
+        match settings.players_sort {
+            PlayerSort::Server => {},
+            PlayerSort::Name => sample.sort_by_cached_key(|player| player.name.to_lowercase()),
+            PlayerSort::NameDesc => sample.sort_by_cached_key(|player| std::cmp::Reverse(player.name.to_lowercase())),
+        }
+        let (list, players): (Vec<_>, Vec<_>) = sample.iter().map(|player| {
+            let mut variables = Context::new("");
+            variables.insert("name", player.name.as_str());
+            variables.insert("id", player.id.as_str());
+            variables.insert("uuid", player.id.as_str());
+            let avatar = settings.avatar_url.render(&variables, Escape::None);
+            variables.insert("avatar", avatar.as_str());
+            (json!({ "name": player.name, "id": player.id, "uuid": player.id, "avatar": avatar }), variables)
+        }).unzip();
+        let shown = players.len().min(settings.max_players.unwrap_or(usize::MAX));
+        // Servers send a sample of the players, so the ones left out include those missing from it
+        let left_out = (self.players.online as usize).saturating_sub(shown);
+        let suffix = (left_out > 0).then(|| {
+            let mut variables = Context::new("");
+            variables.insert("count", left_out);
+            variables.insert("shown", shown);
+            variables.insert("total", self.players.online);
+            (settings.players_overflow.clone(), variables)
+        });
+        let players = Formatted {
+            items: players.into_iter().take(shown).collect(),
+            template: settings.player_template.clone(),
+            separator: settings.players_separator.clone(),
+            suffix,
+        };
+        context.insert("version", self.version.name.as_str());
+        context.insert("protocol", self.version.protocol);
+        context.insert("description", self.description.text.as_str());
+        context.insert("online", self.players.online);
+        context.insert("max", self.players.max);
+        context.insert_formatted("players", list, players);
+        context.insert("hostname", self.target.host.as_str());
+        context.insert("name", self.target.name.as_str());
+        context.insert("host", self.host.as_str());
+        context.insert("port", self.port);
+        context.insert("latency", self.latency);
+        context.insert("tags", self.target.tags.clone());
+        context
+    }
+}
+
+// Formatting of the "%players" placeholder
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerSettings {
+    // Player list separator. Defaults to "\n"
+    #[serde(default = "default_players_separator")]
+    pub players_separator: String,
+
+    // Template of every player, with the "%name", "%uuid" and "%avatar" placeholders. Defaults to "%name"
+    #[serde(default = "default_player_template")]
+    pub player_template: Template,
+
+    // Template of the avatar URL of a player, with the "%name" and "%uuid" placeholders.
+    // Defaults to "https://mc-heads.net/avatar/%uuid"
+    #[serde(default = "default_avatar_url")]
+    pub avatar_url: Template,
+
+    // Order of the players: "server", as sent by the server, "name" or "name_desc". Defaults to "server"
+    #[serde(default)]
+    pub players_sort: PlayerSort,
+
+    // Maximum number of players written
+    pub max_players: Option<usize>,
+
+    // Template written after the players when some are left out, with the "%count" of those left out,
+    // the "%shown" ones and the "%total" online. Defaults to nothing
+    #[serde(default = "default_players_overflow")]
+    pub players_overflow: Template,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerSort {
+    #[default]
+    Server,
+    Name,
+    NameDesc,
+}
+
+impl PlayerSettings {
+    // Default settings with another separator
+    pub fn separated<S: AsRef<str>>(separator: S) -> Self {
+        Self { players_separator: separator.as_ref().to_owned(), ..Default::default() }
+    }
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            players_separator: default_players_separator(),
+            player_template: default_player_template(),
+            avatar_url: default_avatar_url(),
+            players_sort: PlayerSort::default(),
+            max_players: None,
+            players_overflow: default_players_overflow(),
+        }
     }
 }
 
+pub fn default_players_separator() -> String {
+    "\n".to_owned()
+}
+
+fn default_player_template() -> Template {
+    Template::parse("%name").unwrap()
+}
+
+fn default_avatar_url() -> Template {
+    Template::parse("https://mc-heads.net/avatar/%uuid").unwrap()
+}
+
+fn default_players_overflow() -> Template {
+    Template::parse("").unwrap()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Version {
     pub name: String,
@@ -62,11 +210,19 @@ pub struct Player {
     pub id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct Description {
     pub text: String,
 }
 
+// The description is a JSON text component, which may be a plain string or split into extra components.
+// Its styles are kept as formatting codes in the text
+impl<'de> Deserialize<'de> for Description {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self { text: motd::from_component(&Value::deserialize(deserializer)?) })
+    }
+}
+
 // #[derive(Debug, Deserialize)]
 // pub struct ForgeData{
 //     pub channels: Vec<ForgeChannel>,
@@ -0,0 +1,286 @@
+// Parser of the "§" formatting codes of MOTDs and player names
+
+use serde_json::Value;
+
+// Colors of the codes 0 to f
+const COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0x00, 0x00, 0xaa], [0x00, 0xaa, 0x00], [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00], [0xaa, 0x00, 0xaa], [0xff, 0xaa, 0x00], [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55], [0x55, 0x55, 0xff], [0x55, 0xff, 0x55], [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55], [0xff, 0x55, 0xff], [0xff, 0xff, 0x55], [0xff, 0xff, 0xff],
+];
+
+// Names of the colors of the codes 0 to f in JSON text components
+const COLOR_NAMES: [&str; 16] = [
+    "black", "dark_blue", "dark_green", "dark_aqua", "dark_red", "dark_purple", "gold", "gray",
+    "dark_gray", "blue", "green", "aqua", "red", "light_purple", "yellow", "white",
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub color: Option<[u8; 3]>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub strikethrough: bool,
+    pub obfuscated: bool,
+}
+
+// Text drawn with a single style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub text: String,
+    pub style: Style,
+}
+
+// Splits the text into segments, dropping the formatting codes. Colors reset the other formats,
+// as in the game, and "§x§R§R§G§G§B§B" sets a hex color
+pub fn parse(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut style = Style::default();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '§' {
+            current.push(c);
+            continue;
+        }
+        let code = match chars.next() {
+            Some(code) => code.to_ascii_lowercase(),
+            None => break,
+        };
+        let mut next = style.clone();
+        match code {
+            '0'..='9' | 'a'..='f' => next = Style { color: Some(COLORS[code.to_digit(16).unwrap() as usize]), ..Default::default() },
+            'x' => {
+                let digits = chars.clone().collect::<Vec<_>>();
+                let hex = digits.chunks(2).take(6)
+                    .filter(|pair| pair.len() == 2 && pair[0] == '§')
+                    .filter_map(|pair| pair[1].to_digit(16))
+                    .collect::<Vec<_>>();
+                if hex.len() == 6 {
+                    chars.nth(11);
+                    let channel = |index: usize| (hex[index] * 16 + hex[index + 1]) as u8;
+                    next = Style { color: Some([channel(0), channel(2), channel(4)]), ..Default::default() };
+                }
+            },
+            'k' => next.obfuscated = true,
+            'l' => next.bold = true,
+            'm' => next.strikethrough = true,
+            'n' => next.underlined = true,
+            'o' => next.italic = true,
+            'r' => next = Style::default(),
+            _ => {},
+        }
+        if next != style {
+            if !current.is_empty() {
+                segments.push(Segment { text: std::mem::take(&mut current), style: style.clone() });
+            }
+            style = next;
+        }
+    }
+    if !current.is_empty() {
+        segments.push(Segment { text: current, style });
+    }
+    segments
+}
+
+// Text of a JSON text component, like the description of the status, with its styles written as formatting codes.
+// The extra components follow the text and inherit the styles of their parent
+pub fn from_component(component: &Value) -> String {
+    let mut text = String::new();
+    write_component(component, &Style::default(), &mut Some(Style::default()), &mut text);
+    text
+}
+
+// The current style is the one the written codes left, unknown once a text wrote codes of its own
+fn write_component(component: &Value, parent: &Style, current: &mut Option<Style>, output: &mut String) {
+    match component {
+        Value::String(text) => write_styled(text, parent, current, output),
+        Value::Array(components) => {
+            for component in components {
+                write_component(component, parent, current, output);
+            }
+        },
+        Value::Object(map) => {
+            let mut style = parent.clone();
+            if let Some(color) = map.get("color").and_then(Value::as_str).and_then(color) {
+                style.color = Some(color);
+            }
+            for (name, enabled) in [
+                ("bold", &mut style.bold),
+                ("italic", &mut style.italic),
+                ("underlined", &mut style.underlined),
+                ("strikethrough", &mut style.strikethrough),
+                ("obfuscated", &mut style.obfuscated),
+            ] {
+                if let Some(value) = map.get(name).and_then(Value::as_bool) {
+                    *enabled = value;
+                }
+            }
+            if let Some(text) = map.get("text").and_then(Value::as_str) {
+                write_styled(text, &style, current, output);
+            }
+            for extra in map.get("extra").and_then(Value::as_array).into_iter().flatten() {
+                write_component(extra, &style, current, output);
+            }
+        },
+        _ => {},
+    }
+}
+
+// Writes the codes changing the current style before the text. The color comes first, as it resets the other formats
+fn write_styled(text: &str, style: &Style, current: &mut Option<Style>, output: &mut String) {
+    if text.is_empty() {
+        return;
+    }
+    if current.as_ref() != Some(style) {
+        match style.color {
+            Some(color) => match COLORS.iter().position(|known| *known == color) {
+                Some(index) => output.push_str(&format!("§{:x}", index)),
+                None => {
+                    output.push_str("§x");
+                    for digit in format!("{:02x}{:02x}{:02x}", color[0], color[1], color[2]).chars() {
+                        output.push('§');
+                        output.push(digit);
+                    }
+                },
+            },
+            None => output.push_str("§r"),
+        }
+        for (enabled, code) in [(style.obfuscated, "§k"), (style.bold, "§l"), (style.strikethrough, "§m"), (style.underlined, "§n"), (style.italic, "§o")] {
+            if enabled {
+                output.push_str(code);
+            }
+        }
+    }
+    output.push_str(text);
+    *current = (!text.contains('§')).then(|| style.clone());
+}
+
+// Color of a text component, named or as "#RRGGBB"
+fn color(name: &str) -> Option<[u8; 3]> {
+    if let Some(index) = COLOR_NAMES.iter().position(|known| *known == name) {
+        return Some(COLORS[index]);
+    }
+    let hex = name.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Text without the formatting codes
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|segment| segment.text).collect()
+}
+
+// Lines of the text with the styles as ANSI escape codes, each cut to the width in characters
+pub fn ansi(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut length = 0;
+    for segment in parse(text) {
+        for (index, part) in segment.text.split('\n').enumerate() {
+            if index > 0 {
+                lines.last_mut().unwrap().push_str("\x1b[0m");
+                lines.push(String::new());
+                length = 0;
+            }
+            let part = part.chars().take(width.saturating_sub(length)).collect::<String>();
+            if part.is_empty() {
+                continue;
+            }
+            length += part.chars().count();
+            let line = lines.last_mut().unwrap();
+            line.push_str("\x1b[0");
+            let style = &segment.style;
+            if let Some([r, g, b]) = style.color {
+                line.push_str(&format!(";38;2;{};{};{}", r, g, b));
+            }
+            for (enabled, code) in [(style.bold, ";1"), (style.italic, ";3"), (style.underlined, ";4"), (style.strikethrough, ";9")] {
+                if enabled {
+                    line.push_str(code);
+                }
+            }
+            line.push('m');
+            line.push_str(&part);
+        }
+    }
+    lines.last_mut().unwrap().push_str("\x1b[0m");
+    lines
+}
+
+// HTML of the text, with the styles as inline CSS and the lines separated by <br>
+pub fn html(text: &str) -> String {
+    let mut html = String::new();
+    for segment in parse(text) {
+        let style = &segment.style;
+        let mut css = Vec::new();
+        if let Some([r, g, b]) = style.color {
+            css.push(format!("color:#{:02x}{:02x}{:02x}", r, g, b));
+        }
+        if style.bold {
+            css.push("font-weight:bold".to_owned());
+        }
+        if style.italic {
+            css.push("font-style:italic".to_owned());
+        }
+        match (style.underlined, style.strikethrough) {
+            (true, true) => css.push("text-decoration:underline line-through".to_owned()),
+            (true, false) => css.push("text-decoration:underline".to_owned()),
+            (false, true) => css.push("text-decoration:line-through".to_owned()),
+            (false, false) => {},
+        }
+        let text = escape_html(&segment.text).replace('\n', "<br>");
+        match css.is_empty() {
+            true => html.push_str(&text),
+            false => html.push_str(&format!("<span style=\"{}\">{}</span>", css.join(";"), text)),
+        }
+    }
+    html
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_codes() {
+        let segments = parse("A §aGreen §lbold§r plain §x§1§2§3§4§5§6hex");
+        assert_eq!(segments.iter().map(|segment| segment.text.as_str()).collect::<Vec<_>>(), ["A ", "Green ", "bold", " plain ", "hex"]);
+        assert_eq!(segments[1].style.color, Some([0x55, 0xff, 0x55]));
+        assert!(segments[2].style.bold && segments[2].style.color.is_some());
+        assert_eq!(segments[3].style, Style::default());
+        assert_eq!(segments[4].style.color, Some([0x12, 0x34, 0x56]));
+        assert_eq!(strip("§6Gold§r §kx§"), "Gold x");
+    }
+
+    #[test]
+    fn reads_components() {
+        assert_eq!(from_component(&serde_json::json!({ "text": "A §aserver" })), "A §aserver");
+        assert_eq!(from_component(&serde_json::json!("plain")), "plain");
+        let component = serde_json::json!({
+            "text": "",
+            "extra": [
+                { "text": "Gold ", "color": "gold", "bold": true, "extra": [{ "text": "still", "bold": false }] },
+                " plain",
+                { "text": " hex", "color": "#123456" },
+            ],
+        });
+        let text = from_component(&component);
+        assert_eq!(text, "§6§lGold §6still§r plain§x§1§2§3§4§5§6 hex");
+        assert_eq!(parse(&text)[0].style, Style { color: Some(COLORS[6]), bold: true, ..Default::default() });
+    }
+
+    #[test]
+    fn renders_ansi() {
+        assert_eq!(ansi("§lBold §cred\nSecond line", 8), ["\x1b[0;1mBold \x1b[0;38;2;255;85;85mred\x1b[0m", "\x1b[0;38;2;255;85;85mSecond l\x1b[0m"]);
+    }
+
+    #[test]
+    fn renders_html() {
+        assert_eq!(html("§cRed <b>\n§r§nline"), "<span style=\"color:#ff5555\">Red &lt;b&gt;<br></span><span style=\"text-decoration:underline\">line</span>");
+    }
+}
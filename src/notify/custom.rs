@@ -5,16 +5,20 @@ use std::{
 };
 use futures_util::{future::BoxFuture, FutureExt};
 use serde::{Deserialize, Serialize};
-use once_cell::sync::OnceCell;
 use async_std::sync::Arc;
 use serde_json::Value;
 
-use crate::models::Status;
+use crate::{
+    models::{PlayerSettings, Status},
+    reports::Report,
+    template::{self, Escape},
+};
 use super::{
     NotifyService,
-    default_players_separator,
-    try_request,
-    read_config,
+    Event,
+    EventKind,
+    TimeSettings,
+    outbox::Outbox,
 };
 
 #[derive(Debug, Deserialize)]
@@ -28,38 +32,68 @@ struct CustomConfig {
     // Custom data added to the status change request
     custom_data: Option<HashMap<String, Value>>,
 
-    // Player list reparator. Defaults to "\n"
-    #[serde(default = "default_players_separator")]
-    players_separator: String,
+    // Formatting of the "%players" placeholder
+    #[serde(flatten)]
+    players: PlayerSettings,
+
+    // Time zone and formats of the time placeholders
+    #[serde(flatten)]
+    time: TimeSettings,
 }
 
-static CONFIG: OnceCell<CustomConfig> = OnceCell::new();
+#[derive(Debug)]
+pub struct Custom {
+    name: String,
+    config: CustomConfig,
+    outbox: Arc<Outbox>,
+}
 
-pub const INSTANCE: Custom = Custom{};
+impl Custom {
+    pub fn new(name: String, settings: &Value, outbox: Arc<Outbox>) -> Result<Self, Box<dyn Error>> {
+        Ok(Custom { name, config: CustomConfig::deserialize(settings)?, outbox })
+    }
+}
 
-#[derive(Debug)]
-pub struct Custom;
+#[derive(Serialize)]
+struct StatusWithEvent<'a> {
+    #[serde(flatten)]
+    status: &'a Status,
+    event: EventKind,
+    // Status before the event and Unix time of the previous event of the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<&'a Status>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_change: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<&'a Report>,
+}
 
 #[derive(Serialize)]
 struct StatusWithCustomData<'a> {
     status: &'a Status,
     custom_data: HashMap<&'a String, Value>,
+    event: EventKind,
+    // Status before the event and Unix time of the previous event of the target
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous: Option<&'a Status>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_change: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<&'a Report>,
 }
 
 impl NotifyService for Custom {
-    fn init(&self) -> Result<(), Box<dyn Error>> {
-        CONFIG.set(read_config("custom.json")?).unwrap();
-        log::info!("initialized");
-        Ok(())
-    }
-    
-    fn notify(&self, status: Arc<Status>) -> BoxFuture<Result<(), Box<dyn Error>>> {
+    fn notify(&self, event: Arc<Event>) -> BoxFuture<'_, Result<(), Box<dyn Error>>> {
         async move {
-            let config = CONFIG.get().unwrap();
+            let config = &self.config;
+            let status = &event.status;
+            let context = event.context(&config.players, &config.time);
             let mut build = surf::post(&config.url).header("Content-Type", "application/json");
             if let Some(headers) = config.headers.as_ref() {
                 for (header, value) in headers {
-                    build = build.header(header.as_str(), status.format(value, &config.players_separator));
+                    // Line breaks in the template itself would start another header as well
+                    let value = template::render(value, &context, Escape::Header).replace(|c: char| c.is_control(), " ");
+                    build = build.header(header.as_str(), value);
                 }
             }
             let req = build.build();
@@ -67,16 +101,29 @@ impl NotifyService for Custom {
                 let mut custom_data = HashMap::new();
                 for (key, value) in data {
                     if let Value::String(inner_value) = value {
-                        custom_data.insert(key, Value::String(status.format(inner_value, &config.players_separator)));
+                        custom_data.insert(key, Value::String(template::render(inner_value, &context, Escape::None)));
                     } else {
                         custom_data.insert(key, value.clone());
                     }
                 }
-                serde_json::to_vec(&StatusWithCustomData{status: status.as_ref(), custom_data})?
+                serde_json::to_vec(&StatusWithCustomData {
+                    status: status.as_ref(),
+                    custom_data,
+                    event: event.kind,
+                    previous: event.timeline.previous.as_deref(),
+                    last_change: event.timeline.last_change,
+                    report: event.report.as_ref(),
+                })?
             } else {
-                serde_json::to_vec(status.as_ref())?
+                serde_json::to_vec(&StatusWithEvent {
+                    status: status.as_ref(),
+                    event: event.kind,
+                    previous: event.timeline.previous.as_deref(),
+                    last_change: event.timeline.last_change,
+                    report: event.report.as_ref(),
+                })?
             };
-            try_request(req, body, 0).await?;
+            self.outbox.send(req, body).await?;
             Ok(())
         }.boxed()
     }
@@ -84,6 +131,6 @@ impl NotifyService for Custom {
 
 impl Display for Custom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.name)
     }
 }
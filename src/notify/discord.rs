@@ -1,14 +1,28 @@
+use std::error::Error;
+use futures_util::{future::BoxFuture, FutureExt};
 use serde_json::{json, map::Map, Value};
 use serde::{Serialize, Deserialize};
-use async_std::sync::Arc;
-
-use crate::models::Status;
 use super::{
-    WebhookService,
     ApplyStatus,
+    Event,
+    render_map,
+    outbox::Outbox,
+    webhook::{split_text, truncate, Attach, Split},
 };
+use crate::template::{Context, Escape};
+
+const BOUNDARY: &str = "mc-ping-attachment";
+
+// Limits of the messages, in characters
+const CONTENT_LIMIT: usize = 2000;
+const USERNAME_LIMIT: usize = 80;
+const EMBEDS_LIMIT: usize = 10;
+const EMBEDS_TOTAL_LIMIT: usize = 6000;
+const FIELDS_LIMIT: usize = 25;
 
-pub static INSTANCE: WebhookService<Message> =  WebhookService::new("Discord");
+// Limits of the texts of an embed and of its fields, by their JSON pointer
+const EMBED_LIMITS: [(&str, usize); 4] = [("/title", 256), ("/description", 4096), ("/footer/text", 2048), ("/author/name", 256)];
+const FIELD_LIMITS: [(&str, usize); 2] = [("/name", 256), ("/value", 1024)];
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message(Map<String, Value>);
@@ -24,7 +38,122 @@ impl Default for Message {
 }
 
 impl ApplyStatus for Message {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        self.0.apply_status(status, player_separator);
+    fn apply_status(&mut self, _: &Event, context: &Context) {
+        render_map(&mut self.0, context, Escape::Discord);
+    }
+}
+
+// Long content is sent as several messages, followed by the embeds in groups fitting in a message
+impl Split for Message {
+    fn split(mut self) -> Vec<Self> {
+        if let Some(Value::String(username)) = self.0.get_mut("username") {
+            *username = truncate(username, USERNAME_LIMIT);
+        }
+        let content = match self.0.remove("content") {
+            Some(Value::String(content)) => split_text(&content, CONTENT_LIMIT),
+            Some(content) => {
+                self.0.insert("content".to_owned(), content);
+                Vec::new()
+            },
+            None => Vec::new(),
+        };
+        let embeds = match self.0.remove("embeds") {
+            Some(Value::Array(embeds)) => embeds,
+            Some(embeds) => {
+                self.0.insert("embeds".to_owned(), embeds);
+                Vec::new()
+            },
+            None => Vec::new(),
+        };
+        let mut groups = Vec::<Vec<Value>>::new();
+        let mut total = 0;
+        for embed in embeds.into_iter().map(fit_embed) {
+            let length = embed_length(&embed);
+            match groups.last_mut() {
+                Some(group) if group.len() < EMBEDS_LIMIT && total + length <= EMBEDS_TOTAL_LIMIT => group.push(embed),
+                _ => {
+                    groups.push(vec![embed]);
+                    total = 0;
+                },
+            }
+            total += length;
+        }
+        let mut messages = content.into_iter().map(|content| {
+            let mut message = self.0.clone();
+            message.insert("content".to_owned(), Value::String(content));
+            message
+        }).collect::<Vec<_>>();
+        for (index, group) in groups.into_iter().enumerate() {
+            match messages.last_mut() {
+                Some(message) if index == 0 => {
+                    message.insert("embeds".to_owned(), Value::Array(group));
+                },
+                _ => {
+                    let mut message = self.0.clone();
+                    message.insert("embeds".to_owned(), Value::Array(group));
+                    messages.push(message);
+                },
+            }
+        }
+        if messages.is_empty() {
+            messages.push(self.0);
+        }
+        messages.into_iter().map(Message).collect()
+    }
+}
+
+// Cuts the texts of the embed to their limits, and its description to the limit of the embeds of a message
+fn fit_embed(mut embed: Value) -> Value {
+    for (pointer, limit) in EMBED_LIMITS {
+        if let Some(Value::String(text)) = embed.pointer_mut(pointer) {
+            *text = truncate(text, limit);
+        }
+    }
+    if let Some(Value::Array(fields)) = embed.get_mut("fields") {
+        fields.truncate(FIELDS_LIMIT);
+        for field in fields.iter_mut() {
+            for (pointer, limit) in FIELD_LIMITS {
+                if let Some(Value::String(text)) = field.pointer_mut(pointer) {
+                    *text = truncate(text, limit);
+                }
+            }
+        }
+    }
+    let excess = embed_length(&embed).saturating_sub(EMBEDS_TOTAL_LIMIT);
+    if let Some(Value::String(description)) = embed.get_mut("description").filter(|_| excess > 0) {
+        *description = truncate(description, description.chars().count().saturating_sub(excess));
+    }
+    embed
+}
+
+// Characters of the embed counted towards the limit of the embeds of a message
+fn embed_length(embed: &Value) -> usize {
+    let length = |value: Option<&Value>| value.and_then(Value::as_str).map(|text| text.chars().count()).unwrap_or_default();
+    let fields: usize = embed.get("fields").and_then(Value::as_array).map(|fields| {
+        fields.iter().flat_map(|field| FIELD_LIMITS.iter().map(|(pointer, _)| length(field.pointer(pointer)))).sum()
+    }).unwrap_or_default();
+    EMBED_LIMITS.iter().map(|(pointer, _)| length(embed.pointer(pointer))).sum::<usize>() + fields
+}
+
+// Discord needs no settings, the image is sent with the message as a multipart request
+#[derive(Debug, Deserialize)]
+pub struct Settings {}
+
+impl Attach for Message {
+    type Settings = Settings;
+
+    fn send_with_image<'a>(webhook: &'a str, _: &'a Settings, outbox: &'a Outbox, message: &'a Self, name: &'a str, image: &'a [u8]) -> BoxFuture<'a, Result<(), Box<dyn Error>>> {
+        async move {
+            let mut body = Vec::with_capacity(image.len() + 1024);
+            body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\nContent-Type: application/json\r\n\r\n", BOUNDARY).as_bytes());
+            serde_json::to_writer(&mut body, message)?;
+            body.extend_from_slice(format!("\r\n--{}\r\nContent-Disposition: form-data; name=\"files[0]\"; filename=\"{}\"\r\nContent-Type: image/png\r\n\r\n", BOUNDARY, name).as_bytes());
+            body.extend_from_slice(image);
+            body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+            let request = surf::post(webhook)
+                .header("Content-Type", format!("multipart/form-data; boundary={}", BOUNDARY))
+                .build();
+            outbox.send(request, body).await
+        }.boxed()
     }
 }
@@ -1,18 +1,25 @@
-use std::{error::Error, fmt::Display};
+use std::{collections::HashMap, error::Error, fmt::Display};
 use futures_util::{future::BoxFuture, FutureExt};
 use serde_json::{json, map::Map, Value};
 use serde::{Deserialize, Serialize};
-use once_cell::sync::OnceCell;
 use async_std::sync::Arc;
 
-use crate::models::Status;
 use super::{
     ApplyStatus,
     NotifyService,
-    default_players_separator,
-    try_request,
-    read_config,
+    Event,
+    EventKind,
+    TimeSettings,
+    render_map,
+    outbox::Outbox,
 };
+use crate::{
+    models::PlayerSettings,
+    template::{self, Context, Escape},
+};
+
+// Limit of the payload of a notification, in bytes
+const PAYLOAD_LIMIT: usize = 4096;
 
 #[derive(Debug, Deserialize)]
 struct FirebaseConfig {
@@ -22,36 +29,44 @@ struct FirebaseConfig {
     #[serde(flatten)]
     notification: Notification,
 
-    // Player list reparator. Defaults to "\n"
-    #[serde(default = "default_players_separator")]
-    players_separator: String,
-}
-
-static CONFIG: OnceCell<FirebaseConfig> = OnceCell::new();
+    // Formatting of the "%players" placeholder
+    #[serde(flatten)]
+    players: PlayerSettings,
 
-pub const INSTANCE: Firebase = Firebase{};
+    // Time zone and formats of the time placeholders
+    #[serde(flatten)]
+    time: TimeSettings,
+}
 
 #[derive(Debug)]
-pub struct Firebase;
+pub struct Firebase {
+    name: String,
+    config: FirebaseConfig,
+    outbox: Arc<Outbox>,
+}
 
-impl NotifyService for Firebase {
-    fn init(&self) -> Result<(), Box<dyn Error>> {
-        CONFIG.set(read_config("firebase.json")?).unwrap();
-        log::info!("initialized");
-        Ok(())
+impl Firebase {
+    pub fn new(name: String, settings: &Value, outbox: Arc<Outbox>) -> Result<Self, Box<dyn Error>> {
+        Ok(Firebase { name, config: FirebaseConfig::deserialize(settings)?, outbox })
     }
-    
-    fn notify(&self, status: Arc<Status>) -> BoxFuture<Result<(), Box<dyn Error>>> {
+}
+
+impl NotifyService for Firebase {
+    fn notify(&self, event: Arc<Event>) -> BoxFuture<'_, Result<(), Box<dyn Error>>> {
         async move {
-            let config = CONFIG.get().unwrap();
+            let config = &self.config;
             let mut notification = config.notification.clone();
-            notification.apply_status(status, &config.players_separator);
+            if let Some(event_notification) = notification.event_notifications.get(&event.kind) {
+                notification.notification = event_notification.clone();
+                notification.empty_notofication = None;
+            }
+            notification.apply_status(&event, &event.context(&config.players, &config.time));
             let req = surf::post("https://fcm.googleapis.com/fcm/send")
                 .header("Authorization", format!("key={}", config.key))
                 .header("Content-Type", "application/json")
                 .build();
-            let body = serde_json::to_vec(&notification)?;
-            try_request(req, body, 0).await?;
+            let body = notification.payload()?;
+            self.outbox.send(req, body).await?;
             Ok(())
         }.boxed()
     }
@@ -59,7 +74,7 @@ impl NotifyService for Firebase {
 
 impl Display for Firebase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.name)
     }
 }
 
@@ -92,22 +107,47 @@ struct Notification {
     // Notification when there aren't any players online
     #[serde(skip_serializing)]
     empty_notofication: Option<Map<String, Value>>,
+
+    // Notifications for specific events. Take precedence over the other notifications
+    #[serde(skip_serializing, default = "HashMap::new")]
+    event_notifications: HashMap<EventKind, Map<String, Value>>,
 }
 
 impl ApplyStatus for Notification {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        if status.players.online == 0 {
+    fn apply_status(&mut self, event: &Event, context: &Context) {
+        if event.status.players.online == 0 {
             if let Some(empty) = self.empty_notofication.as_ref() {
                 self.notification = empty.clone();
             }
         }
         if let Some(condition) = self.condition.as_mut() {
-            *condition = status.format(&condition, player_separator);
+            *condition = template::render(condition, context, Escape::None);
         }
         if let Some(data) = self.data.as_mut() {
-            data.apply_status(status.clone(), player_separator);
+            render_map(data, context, Escape::None);
+        }
+        render_map(&mut self.notification, context, Escape::None);
+    }
+}
+
+impl Notification {
+    // Serializes the notification, cutting its body until the payload fits in the limit
+    fn payload(&mut self) -> Result<Vec<u8>, serde_json::Error> {
+        loop {
+            let payload = serde_json::to_vec(self)?;
+            let excess = payload.len().saturating_sub(PAYLOAD_LIMIT);
+            match self.notification.get_mut("body") {
+                Some(Value::String(body)) if excess > 0 && !body.is_empty() => {
+                    // Room for the ellipsis, taking 3 bytes
+                    let keep = body.chars().count().saturating_sub(excess + 3);
+                    *body = match keep {
+                        0 => String::new(),
+                        _ => body.chars().take(keep).chain(Some('…')).collect(),
+                    };
+                },
+                _ => return Ok(payload),
+            }
         }
-        self.notification.apply_status(status, player_separator);
     }
 }
 
@@ -131,6 +171,7 @@ impl Default for Notification {
             data: None,
             notification: data.as_object().unwrap().to_owned(),
             empty_notofication: Some(empty.as_object().unwrap().to_owned()),
+            event_notifications: HashMap::new(),
         }
     }
 }
@@ -1,10 +1,28 @@
-use std::{error::Error, fs, fmt::Display};
-use futures_util::future::{BoxFuture, FutureExt};
+// Without any notifier features most of this module is only used by the notifier implementations
+#![cfg_attr(
+    not(any(feature = "firebase", feature = "discord", feature = "slack", feature = "custom")),
+    allow(dead_code, unused_variables, unreachable_code)
+)]
+
+use std::{error::Error, fs, fmt::Display, time::Duration};
+use futures_util::future::BoxFuture;
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::{map::Map, Value};
 use async_std::{sync::Arc, task};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 
-use crate::models::{Status, InternalError};
+use crate::{
+    models::{InternalError, PlayerSettings, Status},
+    config::Config,
+    events,
+    history,
+    metrics,
+    reports::Report,
+    state,
+    template::{self, Context, Escape},
+    time,
+};
 
 #[cfg(any(feature = "discord", feature = "slack"))]
 mod webhook;
@@ -20,43 +38,309 @@ mod slack;
 #[cfg(feature = "custom")]
 mod custom;
 
-static SERVICES: &[&(dyn NotifyService + Send + Sync)] = &[
+mod outbox;
+mod retry;
+mod rules;
+pub use outbox::OutboxConfig;
+pub use rules::Rule;
+use outbox::Outbox;
+use retry::RetrySettings;
+
+// Notifier types compiled into this build
+const TYPES: &[&str] = &[
     #[cfg(feature = "firebase")]
-    &firebase::INSTANCE,
+    "firebase",
     #[cfg(feature = "discord")]
-    &discord::INSTANCE,
+    "discord",
     #[cfg(feature = "slack")]
-    &slack::INSTANCE,
+    "slack",
     #[cfg(feature = "custom")]
-    &custom::INSTANCE,
+    "custom",
 ];
 
-trait NotifyService: Display {
-    fn init(&self) -> Result<(), Box<dyn Error>>;
-    fn notify(&self, status: Arc<Status>) -> BoxFuture<Result<(), Box<dyn Error>>>;
+static NOTIFIERS: OnceCell<Vec<Notifier>> = OnceCell::new();
+static RULES: OnceCell<Vec<Rule>> = OnceCell::new();
+
+trait NotifyService: Display + Send + Sync {
+    fn notify(&self, event: Arc<Event>) -> BoxFuture<'_, Result<(), Box<dyn Error>>>;
 }
 
-pub async fn init() -> Result<(), Box<dyn Error>> {
-    for service in SERVICES {
-        service.init()?;
+struct Notifier {
+    name: String,
+    events: Vec<EventKind>,
+    service: Box<dyn NotifyService>,
+    // Requests of the service, shared with it
+    outbox: Arc<Outbox>,
+}
+
+impl Notifier {
+    fn accepts(&self, kind: EventKind, routed: bool) -> bool {
+        // MOTD changes and reports are only sent to instances asking for them explicitly, or to instances without events a rule routes them to
+        (self.events.is_empty() && (routed || !matches!(kind, EventKind::MotdChanged | EventKind::Report))) || self.events.contains(&kind)
     }
-    Ok(())
 }
 
-pub fn notify(status: Status) {
-    let status = Arc::new(status);
-    for service in SERVICES {
-        let copy = status.clone();
-        task::spawn(async move {
-            if let Err(err) = service.notify(copy).await {
-                log::error!("failed to notify using {} service: {}", service, err)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    // The number of online players changed
+    PlayersChanged,
+    // The server responds again after being down
+    WentUp,
+    // The server stopped responding
+    WentDown,
+    // The server description changed
+    MotdChanged,
+    // A scheduled report was generated
+    Report,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::PlayersChanged => "players_changed",
+            EventKind::WentUp => "went_up",
+            EventKind::WentDown => "went_down",
+            EventKind::MotdChanged => "motd_changed",
+            EventKind::Report => "report",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub status: Arc<Status>,
+    // Unix time of the event
+    pub time: u64,
+    pub timeline: Timeline,
+    // Aggregates of report events
+    pub report: Option<Report>,
+}
+
+// State of a target before an event, kept by its monitor
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    // Status before the event: the last one received, or the offline status after going down
+    pub previous: Option<Arc<Status>>,
+    // Unix time of the previous event of the target
+    pub last_change: Option<u64>,
+    // Unix time the target came up, while it is up
+    pub up_since: Option<u64>,
+    // Unix time of the last status received before the target went down, while it is down
+    pub down_since: Option<u64>,
+}
+
+// Time zone and formats of the time placeholders of a notifier
+#[derive(Debug, Deserialize)]
+pub struct TimeSettings {
+    // IANA time zone, e.g. "Europe/Warsaw". Defaults to "UTC"
+    #[serde(default = "default_timezone", deserialize_with = "deserialize_timezone")]
+    timezone: Tz,
+
+    // strftime format of the "%time" placeholder. Defaults to "%H:%M:%S"
+    #[serde(default = "default_time_format", deserialize_with = "deserialize_format")]
+    time_format: String,
+
+    // strftime format of the "%date" placeholder. Defaults to "%Y-%m-%d"
+    #[serde(default = "default_date_format", deserialize_with = "deserialize_format")]
+    date_format: String,
+}
+
+impl TimeSettings {
+    // Time written by the "%time" placeholder
+    pub fn time(&self, time: u64) -> String {
+        time::format_local(time, self.timezone, &self.time_format)
+    }
+
+    // Date written by the "%date" placeholder
+    pub fn date(&self, time: u64) -> String {
+        time::format_local(time, self.timezone, &self.date_format)
+    }
+}
+
+pub fn default_timezone() -> Tz {
+    Tz::UTC
+}
+
+fn default_time_format() -> String {
+    "%H:%M:%S".to_owned()
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_owned()
+}
+
+pub fn deserialize_timezone<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tz, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(|_| de::Error::custom(format!("unknown time zone '{}'", name)))
+}
+
+fn deserialize_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let format = String::deserialize(deserializer)?;
+    match time::is_valid_format(&format) {
+        true => Ok(format),
+        false => Err(de::Error::custom(format!("invalid time format '{}'", format))),
+    }
+}
+
+impl Event {
+    // Variables of the templates: the status, the event and the report
+    pub fn context(&self, players: &PlayerSettings, settings: &TimeSettings) -> Context {
+        let mut context = self.status.context(players);
+        context.insert("event", self.kind.name());
+        context.insert("time", settings.time(self.time));
+        context.insert("date", settings.date(self.time));
+        context.insert("timestamp", self.time);
+        context.insert("timestamp_iso", time::format_iso(self.time, settings.timezone));
+        let timeline = &self.timeline;
+        let duration = |since: Option<u64>| since.map(|since| time::format_duration(self.time.saturating_sub(since)));
+        context.insert("uptime", duration(timeline.up_since));
+        context.insert("downtime", duration(timeline.down_since));
+        let previous = timeline.previous.as_deref();
+        context.insert("previous_online", previous.map(|status| status.players.online));
+        context.insert("previous_max", previous.map(|status| status.players.max));
+        context.insert("previous_version", previous.map(|status| status.version.name.as_str()));
+        context.insert("previous_description", previous.map(|status| status.description.text.as_str()));
+        // Signed, e.g. "+2", which still compares as a number
+        context.insert("delta", previous.map(|status| {
+            let delta = self.status.players.online as i64 - status.players.online as i64;
+            if delta > 0 { format!("+{}", delta) } else { delta.to_string() }
+        }));
+        let since = timeline.last_change.map(|time| self.time.saturating_sub(time));
+        context.insert("since", since.map(time::format_duration));
+        context.insert("since_seconds", since);
+        if let Some(report) = self.report.as_ref() {
+            report.extend(&mut context, settings);
+        }
+        context
+    }
+}
+
+static REQUEST_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+
+pub async fn init(config: &Config) -> Result<(), Box<dyn Error>> {
+    REQUEST_TIMEOUT.set(Duration::from_secs(config.timeout.request)).unwrap();
+    if let Some(outbox) = config.outbox.as_ref() {
+        outbox::init(outbox)?;
+    }
+    let mut notifiers = Vec::<Notifier>::new();
+    if let Some(instances) = config.notifiers.as_ref() {
+        for instance in instances {
+            let name = instance.name.clone().unwrap_or_else(|| instance.kind.clone());
+            if notifiers.iter().any(|notifier| notifier.name == name) {
+                return Err(InternalError::new(format!("duplicate notifier name '{}'", name)).into());
             }
-        });
+            let settings = Value::Object(instance.settings.clone());
+            let outbox = open_outbox(&name, &settings)?;
+            let service = build(&name, &instance.kind, &settings, outbox.clone())?;
+            notifiers.push(Notifier { name, events: instance.events.clone(), service, outbox });
+        }
+    } else {
+        // Without a notifier list every compiled notifier type reads its own ./<type>.json file
+        for kind in TYPES {
+            let settings = read_config::<Value>(&format!("{}.json", kind))?;
+            // Their messages only describe player changes, so other events have to be asked for
+            let events = match settings.get("events") {
+                Some(events) => Vec::<EventKind>::deserialize(events)?,
+                None => vec![EventKind::PlayersChanged],
+            };
+            let outbox = open_outbox(kind, &settings)?;
+            let service = build(kind, kind, &settings, outbox.clone())?;
+            notifiers.push(Notifier { name: kind.to_string(), events, service, outbox });
+        }
+    }
+    for notifier in notifiers.iter() {
+        log::info!("initialized {} notifier", notifier.service);
     }
+    for rule in config.rules.iter() {
+        if let Some(unknown) = rule.notifiers.iter().find(|name| !notifiers.iter().any(|notifier| &notifier.name == *name)) {
+            return Err(InternalError::new(format!("rule references unknown notifier '{}'", unknown)).into());
+        }
+    }
+    for report in config.reports.iter() {
+        if let Some(unknown) = report.notifiers.iter().find(|name| !notifiers.iter().any(|notifier| &notifier.name == *name)) {
+            return Err(InternalError::new(format!("report '{}' references unknown notifier '{}'", report.name, unknown)).into());
+        }
+    }
+    NOTIFIERS.set(notifiers).ok();
+    RULES.set(config.rules.clone()).ok();
+    for notifier in NOTIFIERS.get().unwrap() {
+        notifier.outbox.start();
+    }
+    Ok(())
 }
 
-fn default_players_separator() -> String {
-    "\n".to_owned()
+// Opens the outbox of a notifier with the retries of its settings
+fn open_outbox(name: &str, settings: &Value) -> Result<Arc<Outbox>, Box<dyn Error>> {
+    let retry = match settings.get("retry") {
+        Some(retry) => RetrySettings::deserialize(retry)?,
+        None => RetrySettings::default(),
+    };
+    Ok(Arc::new(Outbox::open(name, retry)?))
+}
+
+fn build(name: &str, kind: &str, settings: &Value, outbox: Arc<Outbox>) -> Result<Box<dyn NotifyService>, Box<dyn Error>> {
+    let name = name.to_owned();
+    Ok(match kind {
+        #[cfg(feature = "firebase")]
+        "firebase" => Box::new(firebase::Firebase::new(name, settings, outbox)?),
+        #[cfg(feature = "discord")]
+        "discord" => Box::new(WebhookService::<discord::Message>::new(name, settings, outbox)?),
+        #[cfg(feature = "slack")]
+        "slack" => Box::new(WebhookService::<slack::Message>::new(name, settings, outbox)?),
+        #[cfg(feature = "custom")]
+        "custom" => Box::new(custom::Custom::new(name, settings, outbox)?),
+        _ => return Err(InternalError::new(format!("notifier '{}' has an unknown or disabled type '{}'", name, kind)).into()),
+    })
+}
+
+pub fn notify(kind: EventKind, status: Arc<Status>, timeline: Timeline) {
+    let event = Arc::new(Event { kind, status, time: state::now(), timeline, report: None });
+    events::publish(&event);
+    history::record_event(&event);
+    if state::is_muted(&event.status.target.name) {
+        return;
+    }
+    let notifiers = NOTIFIERS.get().map(Vec::as_slice).unwrap_or_default();
+    let rules = RULES.get().map(Vec::as_slice).unwrap_or_default();
+    for notifier in recipients(notifiers, rules, &event) {
+        send(notifier, event.clone());
+    }
+}
+
+// Notifiers the event is sent to. Without any rules every event is sent to every notifier
+fn recipients<'a>(notifiers: &'a [Notifier], rules: &[Rule], event: &Event) -> Vec<&'a Notifier> {
+    let routed = rules::route(rules, event);
+    notifiers.iter().filter(|notifier| {
+        match rules.is_empty() {
+            true => notifier.accepts(event.kind, false),
+            false => routed.contains(&notifier.name.as_str()) && notifier.accepts(event.kind, true),
+        }
+    }).collect()
+}
+
+// Sends a report to the named notifiers, or to the ones accepting reports when none are named
+pub fn report(report: Report, status: Arc<Status>, names: &[String]) {
+    if state::is_muted(&status.target.name) {
+        return;
+    }
+    let event = Arc::new(Event { kind: EventKind::Report, status, time: state::now(), timeline: Timeline::default(), report: Some(report) });
+    for notifier in NOTIFIERS.get().map(Vec::as_slice).unwrap_or_default() {
+        if names.contains(&notifier.name) || (names.is_empty() && notifier.accepts(EventKind::Report, false)) {
+            send(notifier, event.clone());
+        }
+    }
+}
+
+fn send(notifier: &'static Notifier, event: Arc<Event>) {
+    task::spawn(async move {
+        let result = notifier.service.notify(event).await;
+        metrics::record_notification(&notifier.name, result.is_ok());
+        if let Err(err) = result {
+            log::error!("failed to notify using {} notifier: {}", notifier.name, err)
+        }
+    });
 }
 
 fn read_config<T: serde::de::DeserializeOwned>(filename: &str) -> Result<T, Box<dyn Error>> {
@@ -70,79 +354,92 @@ fn read_config<T: serde::de::DeserializeOwned>(filename: &str) -> Result<T, Box<
     }
 }
 
-const MAX_REQUESTS: u8 = 5;
-
 static CLIENT: Lazy<surf::Client> = Lazy::new(|| {
     let client: surf::Client = surf::Config::new()
-        .set_timeout(Some(std::time::Duration::from_secs(5)))
+        .set_timeout(Some(*REQUEST_TIMEOUT.get_or_init(|| Duration::from_secs(5))))
         .set_http_keep_alive(true)
         .try_into().unwrap();
-    client.with(surf::middleware::Redirect::default())
-        .with(surf::middleware::Logger::default())
+    // Redirects are followed by try_request, as the middleware sends every request without its body first
+    client.with(surf::middleware::Logger::default())
 });
 
-fn try_request(request: surf::Request, json: Vec<u8>, attempt: u8) -> BoxFuture<'static, Result<(), Box<dyn Error>>> {
-    async move {
-        let mut fail = false;
-        let mut copy = request.clone();
-        copy.body_bytes(&json);
-        match CLIENT.send(copy).await {
-            Ok(mut res) => {
-                if res.status() != surf::StatusCode::Ok && res.status() != surf::StatusCode::NoContent {
-                    log::warn!("request failed. Status: {}\n{}", res.status(), res.body_string().await?);
-                    fail = true;
-                }
-            }
-            Err(err) => {
-                log::error!("failed to send request: {}", err);
-                fail = true;
-            }
-        }
-        if fail {
-            if attempt < MAX_REQUESTS {
-                try_request(request, json, attempt + 1).await
-            } else {
-                Err(InternalError::new("request failed too many times").into())
-            }
-        } else {
-            Ok(())
-        }
-    }.boxed()
+// Implemented by the messages of the notifiers sending JSON templates
+#[cfg_attr(not(any(feature = "firebase", feature = "discord", feature = "slack")), allow(dead_code))]
+pub trait ApplyStatus {
+    fn apply_status(&mut self, event: &Event, context: &Context);
 }
 
-pub trait ApplyStatus {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S);
+// Renders the templates of every string in the value, escaping the values written for the service
+#[cfg_attr(not(any(feature = "firebase", feature = "discord", feature = "slack")), allow(dead_code))]
+pub fn render_value(value: &mut Value, context: &Context, escape: Escape) {
+    match value {
+        Value::String(text) => {
+            *text = template::render(text, context, escape);
+        },
+        Value::Array(array) => {
+            for value in array.iter_mut() {
+                render_value(value, context, escape);
+            }
+        },
+        Value::Object(map) => {
+            render_map(map, context, escape);
+        },
+        _ => {},
+    }
 }
 
-impl ApplyStatus for Map<String, Value> {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        for (_, value) in self.iter_mut() {
-            value.apply_status(status.clone(), player_separator);
-        }
+#[cfg_attr(not(any(feature = "firebase", feature = "discord", feature = "slack")), allow(dead_code))]
+pub fn render_map(map: &mut Map<String, Value>, context: &Context, escape: Escape) {
+    for (key, value) in map.iter_mut() {
+        // Links, usernames and timestamps are never parsed as markdown, so escaping would only break them
+        let escape = match key.as_str() {
+            "username" | "url" | "timestamp" => Escape::None,
+            key if key.ends_with("_url") => Escape::None,
+            _ => escape,
+        };
+        render_value(value, context, escape);
     }
 }
 
-impl ApplyStatus for Vec<Value> {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        for value in self.iter_mut() {
-            value.apply_status(status.clone(), player_separator);
+#[cfg(test)]
+mod tests {
+    use crate::config::Target;
+    use super::*;
+
+    struct Stub;
+
+    impl Display for Stub {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "stub")
         }
     }
-}
 
-impl ApplyStatus for Value {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        match self {
-            Value::String(text) => {
-                *text = status.format(&text, player_separator);
-            },
-            Value::Array(array) => {
-                array.apply_status(status, player_separator);
-            },
-            Value::Object(map) => {
-                map.apply_status(status, player_separator);
-            },
-            _ => {},
+    impl NotifyService for Stub {
+        fn notify(&self, _: Arc<Event>) -> BoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
         }
     }
+
+    fn notifier(name: &str, events: &[EventKind]) -> Notifier {
+        let outbox = Arc::new(Outbox::open(name, RetrySettings::default()).unwrap());
+        Notifier { name: name.to_owned(), events: events.to_vec(), service: Box::new(Stub), outbox }
+    }
+
+    fn names(notifiers: &[Notifier], rules: &[Rule], kind: EventKind) -> Vec<String> {
+        let status = Status::offline("localhost".to_owned(), 25565, Arc::new(Target::new("localhost".to_owned(), None)));
+        let event = Event { kind, status: Arc::new(status), time: 0, timeline: Default::default(), report: None };
+        recipients(notifiers, rules, &event).into_iter().map(|notifier| notifier.name.clone()).collect()
+    }
+
+    #[test]
+    fn routes_events_to_notifiers() {
+        let notifiers = [notifier("all", &[]), notifier("motd", &[EventKind::MotdChanged]), notifier("down", &[EventKind::WentDown])];
+        assert_eq!(names(&notifiers, &[], EventKind::PlayersChanged), ["all"]);
+        assert_eq!(names(&notifiers, &[], EventKind::MotdChanged), ["motd"]);
+
+        // A rule routing an event to a notifier without events overrides the default filter, but not a list of events
+        let rules = [serde_json::from_value::<Rule>(serde_json::json!({ "events": ["motd_changed"], "notifiers": ["all", "down"] })).unwrap()];
+        assert_eq!(names(&notifiers, &rules, EventKind::MotdChanged), ["all"]);
+        assert_eq!(names(&notifiers, &rules, EventKind::PlayersChanged), Vec::<String>::new());
+    }
 }
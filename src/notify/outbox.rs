@@ -0,0 +1,326 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use async_std::{sync::Mutex as AsyncMutex, task};
+use http_types::{Method, Url};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use super::retry::{try_request, Rejected, RetrySettings};
+use crate::{history, metrics, models::InternalError, state};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutboxConfig {
+    // Directory the outboxes of the notifiers are stored in. Defaults to "outbox"
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    // Time (in seconds) after which undelivered notifications are dropped. Defaults to 86400 (a day)
+    #[serde(default = "default_max_age")]
+    pub max_age: u64,
+
+    // Time (in seconds) between attempts to deliver the pending notifications. Defaults to 60
+    #[serde(default = "default_replay_interval")]
+    pub replay_interval: u64,
+}
+
+fn default_path() -> String {
+    "outbox".to_owned()
+}
+
+fn default_max_age() -> u64 {
+    86400
+}
+
+fn default_replay_interval() -> u64 {
+    60
+}
+
+static CONFIG: OnceCell<OutboxConfig> = OnceCell::new();
+
+// Makes the names of the entries written in the same nanosecond unique
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+pub fn init(config: &OutboxConfig) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&config.path)?;
+    CONFIG.set(config.clone()).ok();
+    Ok(())
+}
+
+// Request journaled before it is sent
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    // Unix time the request was first sent
+    time: u64,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    // Body of the request in base64, as it may be binary
+    body: String,
+}
+
+impl Entry {
+    fn new(request: &surf::Request, body: &[u8]) -> Self {
+        let headers = request.iter()
+            .flat_map(|(name, values)| values.iter().map(move |value| (name.to_string(), value.to_string())))
+            .collect();
+        Self {
+            time: state::now(),
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers,
+            body: base64::encode(body),
+        }
+    }
+
+    fn read(file: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(file)?)?)
+    }
+
+    fn request(&self) -> Result<(surf::Request, Vec<u8>), Box<dyn Error>> {
+        let mut request = surf::Request::new(self.method.parse::<Method>()?, Url::parse(&self.url)?);
+        for (name, value) in self.headers.iter() {
+            request.append_header(name.as_str(), value.as_str());
+        }
+        Ok((request, base64::decode(&self.body)?))
+    }
+}
+
+// Requests of a notifier, journaled on disk until they are delivered when the outbox is enabled
+#[derive(Debug)]
+pub struct Outbox {
+    name: String,
+    retry: RetrySettings,
+    // Directory of the journal, if the outbox is enabled
+    path: Option<PathBuf>,
+    // Held while requests are delivered, so they go out one at a time in order
+    delivering: AsyncMutex<()>,
+    // Outcomes of the journaled requests their senders wait for, set once a delivery handled them
+    outcomes: Mutex<HashMap<PathBuf, Option<Result<(), String>>>>,
+}
+
+impl Outbox {
+    pub fn open(name: &str, retry: RetrySettings) -> Result<Self, Box<dyn Error>> {
+        let path = match CONFIG.get() {
+            Some(config) => {
+                let path = PathBuf::from(&config.path).join(history::file_name(name));
+                fs::create_dir_all(&path)?;
+                // The journal holds the credentials of the requests, so only the owner may read it
+                #[cfg(unix)]
+                fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o700))?;
+                Some(path)
+            },
+            None => None,
+        };
+        let outbox = Outbox { name: name.to_owned(), retry, path, delivering: AsyncMutex::new(()), outcomes: Mutex::new(HashMap::new()) };
+        outbox.record_depth();
+        Ok(outbox)
+    }
+
+    // Retries of the requests sent without the journal, like uploads which can't be sent again later
+    #[cfg_attr(not(feature = "slack"), allow(dead_code))]
+    pub fn retry(&self) -> &RetrySettings {
+        &self.retry
+    }
+
+    // Journals the request and sends it after the pending requests, so the notifications keep their order.
+    // A request which can't be delivered stays in the outbox
+    pub async fn send(&self, request: surf::Request, body: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return try_request(request, body, &self.retry).await,
+        };
+        let file = self.journal(path, &Entry::new(&request, &body))
+            .map_err(|err| log::error!("failed to journal a notification of {}: {}", self.name, err))
+            .ok();
+        let file = match file {
+            Some(file) => file,
+            None => {
+                let _delivering = self.delivering.lock().await;
+                return try_request(request, body, &self.retry).await;
+            },
+        };
+        self.outcomes.lock().unwrap().insert(file.clone(), None);
+        let _delivering = self.delivering.lock().await;
+        // Another delivery may have handled the request while waiting for the lock
+        if self.outcomes.lock().unwrap().get(&file).is_some_and(Option::is_none) {
+            self.deliver(Some(&file)).await;
+        }
+        let outcome = self.outcomes.lock().unwrap().remove(&file).flatten();
+        match outcome {
+            Some(outcome) => outcome.map_err(|err| InternalError::new(err).into()),
+            None if file.exists() => Err(InternalError::new("kept in the outbox behind older pending notifications").into()),
+            None => Err(InternalError::new("the outcome of the notification is unknown").into()),
+        }
+    }
+
+    // Delivers the pending requests in order every replay interval, starting at once
+    pub fn start(self: &Arc<Self>) {
+        let config = match CONFIG.get() {
+            Some(config) => config,
+            None => return,
+        };
+        let pending = self.record_depth();
+        if pending > 0 {
+            log::info!("replaying {} pending notifications of {}", pending, self.name);
+        }
+        let outbox = self.clone();
+        task::spawn(async move {
+            loop {
+                outbox.replay().await;
+                task::sleep(Duration::from_secs(config.replay_interval.max(1))).await;
+            }
+        });
+    }
+
+    async fn replay(&self) {
+        let _delivering = self.delivering.lock().await;
+        // The outcome of the notifications was counted when they were first sent
+        self.deliver(None).await;
+    }
+
+    // Delivers the pending requests in order, up to the given one, setting the outcomes the senders wait for.
+    // When a request still fails after its retries the rest waits for the next replay, to stay in order
+    async fn deliver(&self, until: Option<&Path>) {
+        let max_age = CONFIG.get().map(|config| config.max_age).unwrap_or(u64::MAX);
+        for file in self.pending() {
+            if until.is_some_and(|until| file.as_path() > until) {
+                break;
+            }
+            // Requests journaled before a restart have no sender to report to
+            let waited = self.outcomes.lock().unwrap().contains_key(&file);
+            let entry = Entry::read(&file)
+                .and_then(|entry| Ok((entry.time, entry.request()?)))
+                .map_err(|err| err.to_string());
+            let (time, (request, body)) = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    log::warn!("dropping invalid notification {} of {}: {}", file.display(), self.name, err);
+                    self.finish(&file, Err(format!("invalid notification: {}", err)));
+                    continue;
+                },
+            };
+            let age = state::now().saturating_sub(time);
+            if age > max_age {
+                log::warn!("dropping notification of {} from {} seconds ago", self.name, age);
+                self.finish(&file, Err(format!("dropped after {} seconds in the outbox", age)));
+                continue;
+            }
+            match try_request(request, body, &self.retry).await.map_err(|err| (err.is::<Rejected>(), err.to_string())) {
+                Ok(()) => {
+                    self.finish(&file, Ok(()));
+                    if !waited {
+                        log::info!("delivered a pending notification of {}, {} pending", self.name, self.record_depth());
+                    }
+                },
+                Err((true, err)) => {
+                    if !waited {
+                        log::error!("pending notification of {} rejected: {}", self.name, err);
+                    }
+                    self.finish(&file, Err(err));
+                },
+                Err((false, err)) => {
+                    log::warn!("failed to deliver the notifications of {}, {} kept in the outbox: {}", self.name, self.record_depth(), err);
+                    self.set_outcome(&file, Err(err));
+                    break;
+                },
+            }
+        }
+    }
+
+    // Removes the handled request from the outbox
+    fn finish(&self, file: &Path, outcome: Result<(), String>) {
+        self.remove(file);
+        self.set_outcome(file, outcome);
+    }
+
+    // Keeps the outcome of the request for its sender, if it waits for it
+    fn set_outcome(&self, file: &Path, outcome: Result<(), String>) {
+        if let Some(waiting) = self.outcomes.lock().unwrap().get_mut(file) {
+            *waiting = Some(outcome);
+        }
+    }
+
+    // Writes the entry to a new file, in order of the file names
+    fn journal(&self, path: &Path, entry: &Entry) -> Result<PathBuf, Box<dyn Error>> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let name = format!("{:020}-{:06}", nanos, SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1_000_000);
+        // The entry is written to a temporary file first so a stop while writing leaves no partial entry
+        let temporary = path.join(format!("{}.tmp", name));
+        let file = path.join(format!("{}.json", name));
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut output = options.open(&temporary)?;
+        output.write_all(&serde_json::to_vec(entry)?)?;
+        output.sync_all()?;
+        fs::rename(&temporary, &file)?;
+        self.record_depth();
+        Ok(file)
+    }
+
+    fn remove(&self, file: &Path) {
+        if let Err(err) = fs::remove_file(file) {
+            log::error!("failed to remove a notification of {} from the outbox: {}", self.name, err);
+        }
+        self.record_depth();
+    }
+
+    // Journaled entries in order
+    fn pending(&self) -> Vec<PathBuf> {
+        let mut files = self.entries();
+        files.sort();
+        files
+    }
+
+    fn entries(&self) -> Vec<PathBuf> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|extension| extension == "json"))
+                .collect(),
+            Err(err) => {
+                log::error!("failed to read the outbox of {}: {}", self.name, err);
+                Vec::new()
+            },
+        }
+    }
+
+    // Updates the queue depth metric, returning the depth
+    fn record_depth(&self) -> usize {
+        let depth = self.entries().len();
+        if self.path.is_some() {
+            metrics::record_outbox(&self.name, depth);
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_requests() {
+        let request = surf::post("https://discord.com/api/webhooks/1?wait=true")
+            .header("Content-Type", "application/json")
+            .build();
+        let entry = Entry::new(&request, b"{\"content\":\"1/20\"}");
+        let (restored, body) = serde_json::from_slice::<Entry>(&serde_json::to_vec(&entry).unwrap()).unwrap().request().unwrap();
+        assert_eq!(restored.method(), Method::Post);
+        assert_eq!(restored.url().as_str(), "https://discord.com/api/webhooks/1?wait=true");
+        assert_eq!(restored.header("Content-Type").map(|values| values.as_str()), Some("application/json"));
+        assert_eq!(body, b"{\"content\":\"1/20\"}");
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    error::Error,
+    fmt::Display,
+    hash::{BuildHasher, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+use async_std::task;
+use http_types::{other::RetryAfter, StatusCode, Url};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use super::CLIENT;
+use crate::models::InternalError;
+
+// Redirects followed by a request, like the surf middleware which can't resend the body
+const MAX_REDIRECTS: u8 = 3;
+
+// Rate limit buckets reported by Discord
+static BUCKETS: Lazy<Mutex<Buckets>> = Lazy::new(Default::default);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetrySettings {
+    // Number of times a failed request is sent again. Defaults to 5
+    #[serde(default = "default_retries")]
+    pub retries: u8,
+
+    // Time (in seconds) waited before the first retry, doubled for every next one. Defaults to 1
+    #[serde(default = "default_delay")]
+    pub delay: f64,
+
+    // Longest time (in seconds) waited before a retry. Requests asked to wait longer by the service give up. Defaults to 60
+    #[serde(default = "default_max_delay")]
+    pub max_delay: f64,
+
+    // Fraction (0.0 - 1.0) of the delay taken off at random, so retries of many requests spread out. Defaults to 0.5
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self { retries: default_retries(), delay: default_delay(), max_delay: default_max_delay(), jitter: default_jitter() }
+    }
+}
+
+impl RetrySettings {
+    // Exponential backoff before the given retry, counted from 0, with the random part taken off
+    fn backoff(&self, retry: u8, random: f64) -> Duration {
+        let delay = (self.delay * 2f64.powi(retry as i32)).min(self.max_delay).max(0.0);
+        Duration::from_secs_f64(delay * (1.0 - self.jitter.clamp(0.0, 1.0) * random))
+    }
+}
+
+fn default_retries() -> u8 {
+    5
+}
+
+fn default_delay() -> f64 {
+    1.0
+}
+
+fn default_max_delay() -> f64 {
+    60.0
+}
+
+fn default_jitter() -> f64 {
+    0.5
+}
+
+// Request the service rejected as invalid, which fails the same way when sent again
+#[derive(Debug)]
+pub struct Rejected {
+    status: StatusCode,
+    body: String,
+}
+
+impl Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request rejected. Status: {}\n{}", self.status, self.body)
+    }
+}
+
+impl Error for Rejected {}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    // Bucket of every route, by its URL without the query
+    routes: HashMap<String, String>,
+    // Time until which an exhausted bucket takes no requests
+    resets: HashMap<String, Instant>,
+}
+
+impl Buckets {
+    // Time left until the bucket of the route takes requests again
+    fn wait(&self, route: &str) -> Option<Duration> {
+        let reset = self.resets.get(self.routes.get(route)?)?;
+        reset.checked_duration_since(Instant::now())
+    }
+
+    fn update(&mut self, route: &str, response: &surf::Response) {
+        let header = |name: &str| response.header(name).map(|values| values.last().as_str().to_owned());
+        let bucket = match header("X-RateLimit-Bucket") {
+            Some(bucket) => bucket,
+            None => return,
+        };
+        let remaining = header("X-RateLimit-Remaining").and_then(|remaining| remaining.parse::<u64>().ok());
+        let reset_after = header("X-RateLimit-Reset-After").and_then(|reset| seconds(&reset));
+        match (remaining, reset_after) {
+            (Some(0), Some(reset_after)) => {
+                self.resets.insert(bucket.clone(), Instant::now() + reset_after);
+            },
+            _ => {
+                self.resets.remove(&bucket);
+            },
+        }
+        self.routes.insert(route.to_owned(), bucket);
+    }
+}
+
+// Sends the request with the body, retrying failures with an exponential backoff. Any 2xx response is a success.
+// Client errors other than 429 are returned at once, as sending the same request again would fail the same way
+pub async fn try_request(mut request: surf::Request, body: Vec<u8>, settings: &RetrySettings) -> Result<(), Box<dyn Error>> {
+    let mut retry = 0;
+    let mut redirects = 0;
+    loop {
+        let route = route(request.url());
+        let wait = BUCKETS.lock().unwrap().wait(&route);
+        if let Some(wait) = wait {
+            log::debug!("waiting {:?} for the rate limit of {}", wait, route);
+            task::sleep(wait).await;
+        }
+        let mut copy = request.clone();
+        copy.body_bytes(&body);
+        let asked = match CLIENT.send(copy).await {
+            Ok(mut res) => {
+                BUCKETS.lock().unwrap().update(&route, &res);
+                let status = res.status();
+                if status.is_success() {
+                    return Ok(());
+                }
+                if let (true, Some(location)) = (is_redirect(status), res.header("Location")) {
+                    if redirects < MAX_REDIRECTS {
+                        let location = request.url().join(location.last().as_str())?;
+                        let inner: &mut http_types::Request = request.as_mut();
+                        *inner.url_mut() = location;
+                        redirects += 1;
+                        continue;
+                    }
+                }
+                let asked = retry_after(&res);
+                let text = res.body_string().await.unwrap_or_default();
+                if status.is_client_error() && status != StatusCode::TooManyRequests {
+                    return Err(Rejected { status, body: text }.into());
+                }
+                log::warn!("request failed. Status: {}\n{}", status, text);
+                asked
+            },
+            Err(err) => {
+                log::error!("failed to send request: {}", err);
+                None
+            },
+        };
+        if retry >= settings.retries {
+            return Err(InternalError::new("request failed too many times").into());
+        }
+        let delay = match asked {
+            Some(asked) if asked.as_secs_f64() > settings.max_delay => {
+                return Err(InternalError::new(format!("request asked to wait {:.1}s, longer than the max delay", asked.as_secs_f64())).into());
+            },
+            Some(asked) => asked,
+            None => settings.backoff(retry, random()),
+        };
+        retry += 1;
+        log::info!("retrying request to {} in {:.1}s ({}/{})", route, delay.as_secs_f64(), retry, settings.retries);
+        task::sleep(delay).await;
+    }
+}
+
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(status, StatusCode::MovedPermanently | StatusCode::Found | StatusCode::SeeOther | StatusCode::TemporaryRedirect | StatusCode::PermanentRedirect)
+}
+
+fn route(url: &Url) -> String {
+    format!("{}{}", url.host_str().unwrap_or_default(), url.path())
+}
+
+// Time the service asked to wait before retrying, in seconds or as a date.
+// Discord sends fractions of seconds, and the reset of the bucket when it doesn't send the header
+fn retry_after(response: &surf::Response) -> Option<Duration> {
+    let header = |name: &str| response.header(name).map(|values| values.last().as_str().to_owned());
+    if let Some(after) = header("Retry-After").and_then(|after| seconds(&after)) {
+        return Some(after);
+    }
+    if let Ok(Some(after)) = RetryAfter::from_headers(response) {
+        return Some(after.duration_since(SystemTime::now()).unwrap_or_default());
+    }
+    match response.status() {
+        StatusCode::TooManyRequests => header("X-RateLimit-Reset-After").and_then(|reset| seconds(&reset)),
+        _ => None,
+    }
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    value.trim().parse::<f64>().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+}
+
+// Random number in 0.0 - 1.0, from the random keys std gives every hasher
+fn random() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off() {
+        let settings = RetrySettings { retries: 5, delay: 1.0, max_delay: 5.0, jitter: 0.5 };
+        let delays = (0..4).map(|retry| settings.backoff(retry, 0.0).as_secs_f64()).collect::<Vec<_>>();
+        assert_eq!(delays, [1.0, 2.0, 4.0, 5.0]);
+        assert_eq!(settings.backoff(1, 1.0), Duration::from_secs(1));
+        assert_eq!(settings.backoff(2, 0.5), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn reads_retry_after() {
+        let response = |headers: &[(&str, &str)]| {
+            let mut response = http_types::Response::new(StatusCode::TooManyRequests);
+            for (name, value) in headers {
+                response.insert_header(*name, *value);
+            }
+            surf::Response::from(response)
+        };
+        assert_eq!(retry_after(&response(&[("Retry-After", "2")])), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after(&response(&[("Retry-After", "0.25")])), Some(Duration::from_millis(250)));
+        assert_eq!(retry_after(&response(&[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")])), Some(Duration::ZERO));
+        assert_eq!(retry_after(&response(&[("X-RateLimit-Reset-After", "1.5")])), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_after(&response(&[])), None);
+
+        let mut buckets = Buckets::default();
+        buckets.update("discord.com/api/webhooks/1", &response(&[("X-RateLimit-Bucket", "abc"), ("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset-After", "10")]));
+        assert!(buckets.wait("discord.com/api/webhooks/1").is_some_and(|wait| wait > Duration::from_secs(9)));
+        buckets.update("discord.com/api/webhooks/1", &response(&[("X-RateLimit-Bucket", "abc"), ("X-RateLimit-Remaining", "4")]));
+        assert_eq!(buckets.wait("discord.com/api/webhooks/1"), None);
+    }
+}
@@ -0,0 +1,310 @@
+use std::cmp::Ordering;
+use serde::Deserialize;
+
+use crate::models::{InternalError, Status};
+use super::{Event, EventKind};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rule {
+    // Names of the targets the rule applies to. Matches every target when empty
+    #[serde(default)]
+    pub targets: Vec<String>,
+
+    // Tags of the targets the rule applies to. Matches targets with at least one of the tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    // Events the rule applies to. Matches every event when empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+
+    // Conditions the status has to meet, e.g. "online >= max". All of them have to be met
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+
+    // Names of the notifier instances matching events are sent to
+    pub notifiers: Vec<String>,
+}
+
+impl Rule {
+    pub fn matches(&self, event: &Event) -> bool {
+        let target = &event.status.target;
+        (self.targets.is_empty() || self.targets.contains(&target.name))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| target.tags.contains(tag)))
+            && (self.events.is_empty() || self.events.contains(&event.kind))
+            && self.conditions.iter().all(|condition| condition.evaluate(&event.status))
+    }
+}
+
+// Names of the notifiers the event is routed to by the matching rules, without duplicates
+pub fn route<'a>(rules: &'a [Rule], event: &Event) -> Vec<&'a str> {
+    let mut notifiers = Vec::<&str>::new();
+    for rule in rules.iter().filter(|rule| rule.matches(event)) {
+        for notifier in rule.notifiers.iter() {
+            if !notifiers.contains(&notifier.as_str()) {
+                notifiers.push(notifier);
+            }
+        }
+    }
+    notifiers
+}
+
+const OPERATORS: [(&str, Operator); 6] = [
+    (">=", Operator::GreaterOrEqual),
+    ("<=", Operator::LessOrEqual),
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    (">", Operator::Greater),
+    ("<", Operator::Less),
+];
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Condition {
+    left: Operand,
+    operator: Operator,
+    right: Operand,
+}
+
+impl Condition {
+    pub fn evaluate(&self, status: &Status) -> bool {
+        let ordering = match (self.left.resolve(status), self.right.resolve(status)) {
+            (Resolved::Number(left), Resolved::Number(right)) => left.cmp(&right),
+            (Resolved::Text(left), Resolved::Text(right)) => left.as_str().cmp(right.as_str()),
+            _ => return false,
+        };
+        match self.operator {
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual => ordering != Ordering::Equal,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessOrEqual => ordering != Ordering::Greater,
+        }
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = InternalError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut quoted = false;
+        for (index, char) in value.char_indices() {
+            if char == '"' {
+                quoted = !quoted;
+                continue;
+            }
+            if quoted {
+                continue;
+            }
+            if let Some((symbol, operator)) = OPERATORS.iter().find(|(symbol, _)| value[index..].starts_with(symbol)) {
+                let left = Operand::parse(&value[..index])?;
+                let right = Operand::parse(&value[index + symbol.len()..])?;
+                if left.is_number() != right.is_number() {
+                    return Err(InternalError::new(format!("condition '{}' compares a number with text", value)));
+                }
+                if !left.is_number() && !matches!(operator, Operator::Equal | Operator::NotEqual) {
+                    return Err(InternalError::new(format!("condition '{}' can only compare text with == or !=", value)));
+                }
+                return Ok(Condition { left, operator: *operator, right });
+            }
+        }
+        Err(InternalError::new(format!("condition '{}' has no comparison operator", value)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Variable(Variable),
+    Number(i64),
+    Text(String),
+}
+
+impl Operand {
+    fn parse(raw: &str) -> Result<Self, InternalError> {
+        let raw = raw.trim();
+        if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+            return Ok(Operand::Text(raw[1..raw.len() - 1].to_owned()));
+        }
+        if let Ok(number) = raw.parse::<i64>() {
+            return Ok(Operand::Number(number));
+        }
+        Ok(Operand::Variable(match raw {
+            "online" => Variable::Online,
+            "max" => Variable::Max,
+            "protocol" => Variable::Protocol,
+            "port" => Variable::Port,
+            "version" => Variable::Version,
+            "description" => Variable::Description,
+            "name" => Variable::Name,
+            "host" => Variable::Host,
+            _ => return Err(InternalError::new(format!("unknown condition variable '{}'", raw))),
+        }))
+    }
+
+    fn is_number(&self) -> bool {
+        match self {
+            Operand::Variable(variable) => matches!(variable, Variable::Online | Variable::Max | Variable::Protocol | Variable::Port),
+            Operand::Number(_) => true,
+            Operand::Text(_) => false,
+        }
+    }
+
+    fn resolve(&self, status: &Status) -> Resolved {
+        match self {
+            Operand::Variable(Variable::Online) => Resolved::Number(status.players.online as i64),
+            Operand::Variable(Variable::Max) => Resolved::Number(status.players.max as i64),
+            Operand::Variable(Variable::Protocol) => Resolved::Number(status.version.protocol as i64),
+            Operand::Variable(Variable::Port) => Resolved::Number(status.port as i64),
+            Operand::Variable(Variable::Version) => Resolved::Text(status.version.name.clone()),
+            Operand::Variable(Variable::Description) => Resolved::Text(status.description.text.clone()),
+            Operand::Variable(Variable::Name) => Resolved::Text(status.target.name.clone()),
+            Operand::Variable(Variable::Host) => Resolved::Text(status.host.clone()),
+            Operand::Number(number) => Resolved::Number(*number),
+            Operand::Text(text) => Resolved::Text(text.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Online,
+    Max,
+    Protocol,
+    Port,
+    Version,
+    Description,
+    Name,
+    Host,
+}
+
+enum Resolved {
+    Number(i64),
+    Text(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::config::Target;
+    use super::*;
+
+    fn event(kind: EventKind, name: &str, tags: &[&str], online: u32, max: u32) -> Event {
+        let target = Target {
+            name: name.to_owned(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Target::new("localhost".to_owned(), None)
+        };
+        let mut status = Status::offline("localhost".to_owned(), 25565, Arc::new(target));
+        status.players.online = online;
+        status.players.max = max;
+        status.version.name = "1.20.1".to_owned();
+        Event { kind, status: Arc::new(status), time: 0, timeline: Default::default(), report: None }
+    }
+
+    fn rule(value: serde_json::Value) -> Rule {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn condition(raw: &str) -> Result<Condition, InternalError> {
+        Condition::try_from(raw.to_owned())
+    }
+
+    #[test]
+    fn empty_rule_matches_everything() {
+        let rule = rule(serde_json::json!({ "notifiers": ["all"] }));
+        assert!(rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 1, 20)));
+        assert!(rule.matches(&event(EventKind::WentDown, "creative", &["public"], 0, 0)));
+    }
+
+    #[test]
+    fn matches_target_names() {
+        let rule = rule(serde_json::json!({ "targets": ["survival"], "notifiers": ["survival"] }));
+        assert!(rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 1, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "creative", &[], 1, 20)));
+    }
+
+    #[test]
+    fn matches_any_tag() {
+        let rule = rule(serde_json::json!({ "tags": ["public", "event"], "notifiers": ["public"] }));
+        assert!(rule.matches(&event(EventKind::PlayersChanged, "survival", &["public"], 1, 20)));
+        assert!(rule.matches(&event(EventKind::PlayersChanged, "minigames", &["staff", "event"], 1, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "staging", &["staff"], 1, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "untagged", &[], 1, 20)));
+    }
+
+    #[test]
+    fn matches_events() {
+        let rule = rule(serde_json::json!({ "events": ["went_down"], "notifiers": ["on-call"] }));
+        assert!(rule.matches(&event(EventKind::WentDown, "survival", &[], 0, 0)));
+        assert!(!rule.matches(&event(EventKind::WentUp, "survival", &[], 0, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 3, 20)));
+    }
+
+    #[test]
+    fn requires_every_condition() {
+        let rule = rule(serde_json::json!({
+            "conditions": ["online >= max", "max > 0"],
+            "notifiers": ["full"],
+        }));
+        assert!(rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 20, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 19, 20)));
+        assert!(!rule.matches(&event(EventKind::PlayersChanged, "survival", &[], 0, 0)));
+    }
+
+    #[test]
+    fn evaluates_operators() {
+        let status = event(EventKind::PlayersChanged, "survival", &[], 5, 20).status;
+        for (raw, expected) in [
+            ("online == 5", true),
+            ("online != 5", false),
+            ("online > 4", true),
+            ("online > 5", false),
+            ("online >= 5", true),
+            ("online < 5", false),
+            ("online <= 5", true),
+            ("max<online", false),
+            ("version == \"1.20.1\"", true),
+            ("name != \"survival\"", false),
+            ("description == \"a >= b\"", false),
+        ] {
+            assert_eq!(condition(raw).unwrap().evaluate(&status), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert!(condition("online").is_err());
+        assert!(condition("players >= 1").is_err());
+        assert!(condition("online == \"five\"").is_err());
+        assert!(condition("version > \"1.20\"").is_err());
+        assert!(serde_json::from_value::<Rule>(serde_json::json!({
+            "conditions": ["online =< max"],
+            "notifiers": [],
+        })).is_err());
+    }
+
+    #[test]
+    fn routes_to_unique_notifiers() {
+        let rules = vec![
+            rule(serde_json::json!({ "targets": ["survival"], "notifiers": ["survival", "admin"] })),
+            rule(serde_json::json!({ "events": ["went_down"], "notifiers": ["on-call", "admin"] })),
+            rule(serde_json::json!({ "tags": ["staff"], "notifiers": ["staff"] })),
+        ];
+        assert_eq!(route(&rules, &event(EventKind::WentDown, "survival", &[], 0, 0)), vec!["survival", "admin", "on-call"]);
+        assert_eq!(route(&rules, &event(EventKind::PlayersChanged, "survival", &[], 1, 20)), vec!["survival", "admin"]);
+        assert_eq!(route(&rules, &event(EventKind::WentDown, "creative", &[], 0, 0)), vec!["on-call", "admin"]);
+        assert!(route(&rules, &event(EventKind::WentUp, "creative", &[], 0, 20)).is_empty());
+    }
+}
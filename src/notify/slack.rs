@@ -1,14 +1,30 @@
+use std::error::Error;
+use futures_util::{future::BoxFuture, FutureExt};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde_json::{json, map::Map, Value};
 use serde::{Serialize, Deserialize};
-use async_std::sync::Arc;
-
-use crate::models::Status;
 use super::{
-    WebhookService,
     ApplyStatus,
+    Event,
+    CLIENT,
+    render_map,
+    outbox::Outbox,
+    retry::try_request,
+    webhook::{self, split_text, truncate, Attach, Split},
+};
+use crate::{
+    models::InternalError,
+    template::{Context, Escape},
 };
 
-pub static INSTANCE: WebhookService<Message> = WebhookService::new("Slack");
+const API: &str = "https://slack.com/api";
+
+// Limits of the messages, in characters
+const TEXT_LIMIT: usize = 40000;
+const SECTION_LIMIT: usize = 3000;
+const FIELD_LIMIT: usize = 2000;
+const HEADER_LIMIT: usize = 150;
+const BLOCKS_LIMIT: usize = 50;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message(Map<String, Value>);
@@ -31,7 +47,112 @@ impl Default for Message {
 }
 
 impl ApplyStatus for Message {
-    fn apply_status<S: AsRef<str>>(&mut self, status: Arc<Status>, player_separator: &S) {
-        self.0.apply_status(status, player_separator);
+    fn apply_status(&mut self, _: &Event, context: &Context) {
+        render_map(&mut self.0, context, Escape::Slack);
+    }
+}
+
+// Long sections are split into several sections, and the blocks into messages fitting their limit
+impl Split for Message {
+    fn split(mut self) -> Vec<Self> {
+        if let Some(Value::String(text)) = self.0.get_mut("text") {
+            *text = truncate(text, TEXT_LIMIT);
+        }
+        let blocks = match self.0.remove("blocks") {
+            Some(Value::Array(blocks)) if !blocks.is_empty() => blocks,
+            Some(blocks) => {
+                self.0.insert("blocks".to_owned(), blocks);
+                return vec![self];
+            },
+            None => return vec![self],
+        };
+        let blocks = blocks.into_iter().flat_map(fit_block).collect::<Vec<_>>();
+        blocks.chunks(BLOCKS_LIMIT).map(|blocks| {
+            let mut message = self.0.clone();
+            message.insert("blocks".to_owned(), Value::Array(blocks.to_vec()));
+            Message(message)
+        }).collect()
+    }
+}
+
+// Cuts the texts of the block to their limits, splitting a long section into several sections.
+// Only the first section keeps the ID, fields and accessory of the block
+fn fit_block(mut block: Value) -> Vec<Value> {
+    if let Some(Value::Array(fields)) = block.get_mut("fields") {
+        for field in fields.iter_mut() {
+            if let Some(Value::String(text)) = field.get_mut("text") {
+                *text = truncate(text, FIELD_LIMIT);
+            }
+        }
+    }
+    let header = block["type"] == "header";
+    let text = match block.pointer_mut("/text/text") {
+        Some(Value::String(text)) => std::mem::take(text),
+        _ => return vec![block],
+    };
+    if header {
+        block["text"]["text"] = Value::String(truncate(&text, HEADER_LIMIT));
+        return vec![block];
+    }
+    split_text(&text, SECTION_LIMIT).into_iter().enumerate().map(|(index, text)| {
+        let mut part = block.clone();
+        if let (Some(part), true) = (part.as_object_mut(), index > 0) {
+            for key in ["block_id", "fields", "accessory"] {
+                part.remove(key);
+            }
+        }
+        part["text"]["text"] = Value::String(text);
+        part
+    }).collect()
+}
+
+// Webhooks can't carry files, so images are uploaded with the Web API when a bot token is given
+#[derive(Debug, Deserialize)]
+pub struct Settings {
+    // Bot token with the "files:write" scope
+    token: Option<String>,
+
+    // ID of the channel images are shared to
+    channel: Option<String>,
+}
+
+impl Attach for Message {
+    type Settings = Settings;
+
+    fn send_with_image<'a>(webhook: &'a str, settings: &'a Settings, outbox: &'a Outbox, message: &'a Self, name: &'a str, image: &'a [u8]) -> BoxFuture<'a, Result<(), Box<dyn Error>>> {
+        async move {
+            webhook::send(webhook, message, outbox).await?;
+            let (token, channel) = match (settings.token.as_ref(), settings.channel.as_ref()) {
+                (Some(token), Some(channel)) => (token, channel),
+                _ => {
+                    log::warn!("not uploading {} to Slack without a token and a channel", name);
+                    return Ok(());
+                },
+            };
+            let url = format!("{}/files.getUploadURLExternal?filename={}&length={}", API, utf8_percent_encode(name, NON_ALPHANUMERIC), image.len());
+            let upload = call(surf::post(url), token).await?;
+            let (upload_url, file_id) = match (upload["upload_url"].as_str(), upload["file_id"].as_str()) {
+                (Some(upload_url), Some(file_id)) => (upload_url, file_id),
+                _ => return Err(InternalError::new("Slack didn't return an upload URL").into()),
+            };
+            let request = surf::post(upload_url)
+                .header("Content-Type", "application/octet-stream")
+                .build();
+            // Upload URLs expire, so the upload isn't kept in the outbox
+            try_request(request, image.to_vec(), outbox.retry()).await?;
+            let complete = json!({ "files": [{ "id": file_id, "title": name }], "channel_id": channel });
+            call(surf::post(format!("{}/files.completeUploadExternal", API)).body_json(&complete)?, token).await?;
+            Ok(())
+        }.boxed()
+    }
+}
+
+// Calls a Web API method, failing when the response isn't "ok"
+async fn call(request: surf::RequestBuilder, token: &str) -> Result<Value, Box<dyn Error>> {
+    let request = request.header("Authorization", format!("Bearer {}", token)).build();
+    let response: Value = CLIENT.send(request).await?.body_json().await?;
+    match response["ok"].as_bool() {
+        Some(true) => Ok(response),
+        _ => Err(InternalError::new(format!("Slack API error: {}", response["error"].as_str().unwrap_or("unknown"))).into()),
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     error::Error,
     fmt::{
         Debug,
@@ -7,20 +8,34 @@ use std::{
 };
 use futures_util::{future::BoxFuture, FutureExt};
 use serde::{Serialize, Deserialize, de::DeserializeOwned};
-use once_cell::sync::OnceCell;
+use serde_json::Value;
 use async_std::sync::Arc;
 
-use crate::models::Status;
+use crate::models::{InternalError, PlayerSettings};
 use super::{
     ApplyStatus,
     NotifyService,
-    default_players_separator,
-    read_config,
-    try_request,
+    Event,
+    EventKind,
+    TimeSettings,
+    outbox::Outbox,
 };
 
+// Webhook messages able to carry an image, sent the way the service accepts files
+pub trait Attach: Sized {
+    type Settings: DeserializeOwned + Debug + Send + Sync;
+
+    fn send_with_image<'a>(webhook: &'a str, settings: &'a Self::Settings, outbox: &'a Outbox, message: &'a Self, name: &'a str, image: &'a [u8]) -> BoxFuture<'a, Result<(), Box<dyn Error>>>;
+}
+
+// Webhook messages kept within the limits of the service, as it rejects longer ones
+pub trait Split: Sized {
+    // Messages sent in order instead of the message, cut or split to the limits
+    fn split(self) -> Vec<Self>;
+}
+
 #[derive(Debug, Deserialize)]
-struct WebhookConfig<D: ApplyStatus + Default + Debug + Send + Sync + Clone> {
+struct WebhookConfig<D: ApplyStatus + Attach + Split + Default + Debug + Send + Sync + Clone> {
     // Webhook URL
     webhook: String,
 
@@ -31,34 +46,43 @@ struct WebhookConfig<D: ApplyStatus + Default + Debug + Send + Sync + Clone> {
     // Webhook request body when there aren't any players online
     empty_message: Option<D>,
 
-    // Player list reparator. Defaults to "\n"
-    #[serde(default = "default_players_separator")]
-    players_separator: String,
+    // Webhook request bodies for specific events. Take precedence over the other messages
+    #[serde(default = "HashMap::new")]
+    event_messages: HashMap<EventKind, D>,
+
+    // Formatting of the "%players" placeholder
+    #[serde(flatten)]
+    players: PlayerSettings,
+
+    // Time zone and formats of the time placeholders
+    #[serde(flatten)]
+    time: TimeSettings,
+
+    // Settings the service needs to send images
+    #[serde(flatten)]
+    attach: D::Settings,
 }
 
 #[derive(Debug)]
-pub struct WebhookService<D: ApplyStatus + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> {
-    name: &'static str,
-    config: OnceCell<WebhookConfig<D>>,
+pub struct WebhookService<D: ApplyStatus + Attach + Split + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> {
+    name: String,
+    config: WebhookConfig<D>,
+    outbox: Arc<Outbox>,
 }
 
-impl<D: ApplyStatus + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> WebhookService<D> {
-    pub const fn new(name: &'static str) -> Self {
-        WebhookService { name, config: OnceCell::new() }
+impl<D: ApplyStatus + Attach + Split + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> WebhookService<D> {
+    pub fn new(name: String, settings: &Value, outbox: Arc<Outbox>) -> Result<Self, Box<dyn Error>> {
+        Ok(WebhookService { name, config: WebhookConfig::deserialize(settings)?, outbox })
     }
 }
 
-impl<D: ApplyStatus + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> NotifyService for WebhookService<D> {
-    fn init(&self) -> Result<(), Box<dyn Error>> {
-        self.config.set(read_config(&format!("{}.json", self.name.to_lowercase()))?).unwrap();
-        log::info!("initialized {}", self.name);
-        Ok(())
-    }
-
-    fn notify(&self, status: Arc<Status>) -> BoxFuture<Result<(), Box<dyn Error>>>{
+impl<D: ApplyStatus + Attach + Split + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> NotifyService for WebhookService<D> {
+    fn notify(&self, event: Arc<Event>) -> BoxFuture<'_, Result<(), Box<dyn Error>>>{
         async move {
-            let config = self.config.get().unwrap();
-            let message = if status.players.online == 0 {
+            let config = &self.config;
+            let message = if let Some(message) = config.event_messages.get(&event.kind) {
+                message
+            } else if event.status.players.online == 0 {
                 if let Some(empty) = config.empty_message.as_ref() {
                     empty
                 } else {
@@ -68,19 +92,92 @@ impl<D: ApplyStatus + DeserializeOwned + Serialize + Default + Debug + Send + Sy
                 &config.message
             };
             let mut prepared_message = message.clone();
-            prepared_message.apply_status(status, &config.players_separator);
-            let request = surf::post(&self.config.get().unwrap().webhook)
-                .header("Content-Type", "application/json")
-                .build();
-            let body = serde_json::to_vec(&prepared_message)?;
-            try_request(request, body, 0).await?;
-            Ok(())
+            prepared_message.apply_status(&event, &event.context(&config.players, &config.time));
+            let messages = prepared_message.split();
+            let count = messages.len();
+            // Every part is sent even when one fails, as the outbox keeps the failed ones and the parts after them in order
+            let mut failed = Vec::new();
+            for (index, message) in messages.iter().enumerate() {
+                // The chart is sent with the last message
+                let result = match event.report.as_ref().and_then(|report| report.chart.as_ref()) {
+                    Some(chart) if index + 1 == count => D::send_with_image(&config.webhook, &config.attach, &self.outbox, message, "chart.png", chart).await,
+                    _ => send(&config.webhook, message, &self.outbox).await,
+                };
+                if let Err(err) = result {
+                    failed.push(format!("part {}/{}: {}", index + 1, count, err));
+                }
+            }
+            match failed.is_empty() {
+                true => Ok(()),
+                false => Err(InternalError::new(failed.join("\n")).into()),
+            }
         }.boxed()
     }
 }
 
-impl<D: ApplyStatus + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> Display for WebhookService<D> {
+impl<D: ApplyStatus + Attach + Split + DeserializeOwned + Serialize + Default + Debug + Send + Sync + Clone> Display for WebhookService<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
     }
 }
+
+// Posts the message as the JSON body of the webhook request
+pub async fn send<M: Serialize>(webhook: &str, message: &M, outbox: &Outbox) -> Result<(), Box<dyn Error>> {
+    let request = surf::post(webhook)
+        .header("Content-Type", "application/json")
+        .build();
+    let body = serde_json::to_vec(message)?;
+    outbox.send(request, body).await
+}
+
+// Text cut to the limit in characters, ending with an ellipsis when cut
+pub fn truncate(text: &str, limit: usize) -> String {
+    match text.chars().count() > limit {
+        true => text.chars().take(limit.saturating_sub(1)).chain(Some('…')).collect(),
+        false => text.to_owned(),
+    }
+}
+
+// Splits the text into parts of at most limit characters, at a line break or a space in the second
+// half of a part when there is one. Code blocks cut by a split are closed and opened again in the next part
+pub fn split_text(text: &str, limit: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.to_owned();
+    // Room to close a code block
+    let room = limit.saturating_sub(3).max(1);
+    while rest.chars().count() > limit {
+        let end = rest.char_indices().nth(room).map(|(index, _)| index).unwrap_or(rest.len());
+        let half = rest.char_indices().nth(room / 2).map(|(index, _)| index).unwrap_or(0);
+        // The separator dropped by a cut may directly follow the part
+        let head = &rest[..rest.char_indices().nth(room + 1).map(|(index, _)| index).unwrap_or(rest.len())];
+        let (cut, skip) = match head.rfind('\n').filter(|index| *index > half).or_else(|| head.rfind(' ').filter(|index| *index > half)) {
+            Some(index) => (index, 1),
+            None => (end, 0),
+        };
+        let mut part = rest[..cut].to_owned();
+        let mut next = rest[cut + skip..].to_owned();
+        if part.matches("```").count() % 2 == 1 {
+            part.push_str("```");
+            next.insert_str(0, "```\n");
+        }
+        parts.push(part);
+        rest = next;
+    }
+    parts.push(rest);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_text() {
+        assert_eq!(split_text("short", 10), ["short"]);
+        assert_eq!(split_text("one two three four", 10), ["one two", "three four"]);
+        assert_eq!(split_text("abcdefghijklmn", 10), ["abcdefg", "hijklmn"]);
+        assert_eq!(split_text("a\n```\nAlex\nSteve\nHerobrine\n```", 17), ["a\n```\nAlex```", "```\nSteve```", "```\nHerobrine\n```"]);
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("abcd", 4), "abcd");
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use async_std::task;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::{
+    history,
+    models::InternalError,
+    motd::{self, escape_html},
+    state::{self, TargetState},
+    template::{self, Context, Escape},
+    time,
+};
+
+const DEFAULT_TEMPLATE: &str = include_str!("../assets/page.html");
+const DEFAULT_TARGET_TEMPLATE: &str = include_str!("../assets/page_target.html");
+
+// Shown instead of the favicon of servers without one
+const EMPTY_FAVICON: &str = "data:image/gif;base64,R0lGODlhAQABAAAAACH5BAEKAAEALAAAAAABAAEAAAICTAEAOw==";
+
+// Time between the checks for changes to render
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Time after which the uptime bars are read from the history again
+const UPTIME_INTERVAL: u64 = 5 * 60;
+
+// Hours covered by the uptime bar
+const UPTIME_HOURS: u64 = 24;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PageConfig {
+    // Directory the page is written to as index.html
+    pub path: String,
+
+    // Title of the page. Defaults to "Server status"
+    #[serde(default = "default_title")]
+    pub title: String,
+
+    // HTML file used as the template of the page instead of the default one
+    pub template: Option<String>,
+
+    // HTML file used as the template of every target instead of the default one
+    pub target_template: Option<String>,
+}
+
+fn default_title() -> String {
+    "Server status".to_owned()
+}
+
+// Set whenever the state of a target changed since the page was rendered
+static CHANGED: AtomicBool = AtomicBool::new(true);
+
+// Uptime percentage and bar of a target, as read from the history
+struct Uptime {
+    time: u64,
+    percent: String,
+    bar: String,
+}
+
+static UPTIME: Lazy<Mutex<HashMap<String, Uptime>>> = Lazy::new(Default::default);
+
+// Reads the templates and renders the page in the background whenever a target changed
+pub fn init(config: &PageConfig) -> Result<(), Box<dyn Error>> {
+    let read = |path: Option<&String>, default: &str| match path {
+        Some(path) => fs::read_to_string(path).map_err(|err| InternalError::new(format!("unable to read the {} template: {}", path, err))),
+        None => Ok(default.to_owned()),
+    };
+    let template = read(config.template.as_ref(), DEFAULT_TEMPLATE)?;
+    let target_template = read(config.target_template.as_ref(), DEFAULT_TARGET_TEMPLATE)?;
+    fs::create_dir_all(&config.path)?;
+    let config = config.clone();
+    task::spawn(async move {
+        loop {
+            task::sleep(CHECK_INTERVAL).await;
+            if !CHANGED.swap(false, Ordering::Relaxed) {
+                continue;
+            }
+            let now = state::now();
+            // The uptime is read from the history on a blocking thread
+            let (config, template, target_template) = (config.clone(), template.clone(), target_template.clone());
+            task::spawn_blocking(move || {
+                let page = render(&config, &template, &target_template, now);
+                if let Err(err) = write(&config.path, &page) {
+                    log::error!("failed to write the status page to {}: {}", config.path, err);
+                }
+            }).await;
+        }
+    });
+    Ok(())
+}
+
+// Marks the page to be rendered again
+pub fn update() {
+    CHANGED.store(true, Ordering::Relaxed);
+}
+
+// The page replaces the file at once so it is never served half written
+fn write(path: &str, page: &str) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(path).join("index.html");
+    let temporary = path.with_extension("html.tmp");
+    fs::write(&temporary, page)?;
+    fs::rename(&temporary, &path)?;
+    Ok(())
+}
+
+// The values are HTML already, so the templates write them as they are
+fn render(config: &PageConfig, template: &str, target_template: &str, now: u64) -> String {
+    let targets = state::all().iter()
+        .map(|target| template::render(target_template, &context(&target_values(target, now)), Escape::None))
+        .collect::<String>();
+    template::render(template, &context(&[
+        ("title", escape_html(&config.title)),
+        ("updated", format!("{} UTC", time::format_time(now))),
+        ("targets", targets),
+    ]), Escape::None)
+}
+
+fn context(values: &[(&str, String)]) -> Context {
+    let mut context = Context::new("");
+    for (name, value) in values {
+        context.insert(name, value.as_str());
+    }
+    context
+}
+
+fn target_values(target: &TargetState, now: u64) -> Vec<(&'static str, String)> {
+    let status = target.status.as_ref();
+    let players = status.and_then(|status| status.players.sample.as_ref())
+        .map(|sample| sample.iter().map(|player| format!("<li>{}</li>", motd::html(&player.name))).collect())
+        .unwrap_or_default();
+    let (uptime, uptime_bar) = uptime(&target.name, now);
+    vec![
+        ("name", escape_html(&target.name)),
+        ("host", escape_html(&target.host)),
+        ("port", target.port.to_string()),
+        ("state", if target.up { "up" } else { "down" }.to_owned()),
+        ("favicon", status.and_then(|status| status.favicon.clone()).map(|favicon| escape_html(&favicon)).unwrap_or_else(|| EMPTY_FAVICON.to_owned())),
+        ("motd", status.map(|status| motd::html(&status.description.text)).unwrap_or_default()),
+        ("version", status.map(|status| escape_html(&status.version.name)).unwrap_or_default()),
+        ("online", status.map(|status| status.players.online).unwrap_or_default().to_string()),
+        ("max", status.map(|status| status.players.max).unwrap_or_default().to_string()),
+        ("players", players),
+        ("uptime", uptime),
+        ("uptime_bar", uptime_bar),
+        ("last_checked", target.last_checked.map(|time| format!("{} UTC", time::format_time(time))).unwrap_or_else(|| "never".to_owned())),
+    ]
+}
+
+// Uptime percentage and one bar segment per hour from the history, if recorded
+fn uptime(target: &str, now: u64) -> (String, String) {
+    let config = match history::config() {
+        Some(config) => config,
+        None => return ("n/a".to_owned(), String::new()),
+    };
+    let mut cache = UPTIME.lock().unwrap();
+    if let Some(uptime) = cache.get(target).filter(|uptime| now < uptime.time + UPTIME_INTERVAL) {
+        return (uptime.percent.clone(), uptime.bar.clone());
+    }
+    let start = (now / 3600 + 1 - UPTIME_HOURS) * 3600;
+    let history = match history::query(config, target, start, now, Some(3600)) {
+        Ok(history) => history,
+        Err(err) => {
+            log::error!("failed to read the history of {} for the status page: {}", target, err);
+            return ("n/a".to_owned(), String::new());
+        },
+    };
+    let percent = match history.summary.pings {
+        0 => "n/a".to_owned(),
+        _ => format!("{:.1}%", history.summary.uptime_percent),
+    };
+    let bar = (0..UPTIME_HOURS).map(|hour| {
+        let time = start + hour * 3600;
+        let hour = &time::format_time(time)[11..16];
+        match history.samples.iter().find(|sample| sample.time == time) {
+            Some(sample) => {
+                let class = match sample.uptime {
+                    uptime if uptime >= 0.99 => "good",
+                    uptime if uptime >= 0.9 => "partial",
+                    _ => "bad",
+                };
+                format!("<div class=\"{}\" title=\"{} UTC: {:.1}%\"></div>", class, hour, sample.uptime * 100.0)
+            },
+            None => format!("<div title=\"{} UTC: no data\"></div>", hour),
+        }
+    }).collect::<String>();
+    cache.insert(target.to_owned(), Uptime { time: now, percent: percent.clone(), bar: bar.clone() });
+    (percent, bar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders() {
+        let values = [("uptime", "99%".to_owned()), ("uptime_bar", "<div></div>".to_owned()), ("name", "%uptime".to_owned())];
+        let page = template::render("%name: %uptime %uptime_bar 100% %other {{ name }}", &context(&values), Escape::None);
+        assert_eq!(page, "%uptime: 99% <div></div> 100% %other %uptime");
+    }
+}
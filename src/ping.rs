@@ -0,0 +1,284 @@
+use std::{
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+    error::Error,
+    fmt::Display,
+    sync::Arc,
+};
+use async_std::{
+    net::{
+        TcpStream,
+        UdpSocket,
+        Shutdown,
+    },
+    io::{
+        WriteExt,
+        ReadExt,
+    },
+    future,
+};
+use resolv::{
+    record::SRV,
+    RecordType,
+    Resolver,
+    Class,
+};
+
+use crate::{
+    config::{Edition, Target},
+    models::{
+        InternalError,
+        Status,
+        Version,
+        Players,
+        Description,
+    },
+};
+
+// Address of a target after resolving SRV records
+pub struct Address {
+    pub host: String,
+    pub port: u16,
+    pub target: Arc<Target>,
+    handshake: Vec<u8>,
+}
+
+impl Address {
+    pub fn resolve(target: Arc<Target>) -> Self {
+        let mut host = target.host.clone();
+        let mut port = target.port();
+        if target.edition == Edition::Java {
+            resolve_srv(&mut host, &mut port);
+        }
+        let handshake = handshake(&host, &port);
+        Self { host, port, target, handshake }
+    }
+
+    pub async fn ping(&self, timeout: Duration) -> Result<Status, Box<dyn Error + Send + Sync>> {
+        let start = Instant::now();
+        let mut status = match self.target.edition {
+            Edition::Java => ping(&self.handshake, &self.host, &self.port, timeout).await?,
+            Edition::Bedrock => ping_bedrock(&self.host, &self.port, timeout).await?,
+        };
+        status.response_time = start.elapsed().as_millis() as u64;
+        status.host = self.host.clone();
+        status.port = self.port;
+        status.target = self.target.clone();
+        Ok(status)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorKind {
+    // The server could not be reached
+    Unreachable,
+    // The server did not respond in time
+    Timeout,
+    // The server responded with invalid data
+    Protocol,
+}
+
+impl ErrorKind {
+    pub fn of(err: &(dyn Error + 'static)) -> Self {
+        if err.is::<std::io::Error>() {
+            ErrorKind::Unreachable
+        } else if err.is::<future::TimeoutError>() {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::Protocol
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ErrorKind::Unreachable => "unreachable",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Protocol => "protocol",
+        })
+    }
+}
+
+fn resolve_srv(host: &mut String, port: &mut u16) {
+    let dname = format!("_minecraft._tcp.{}", host);
+    if let Ok(mut res) = Resolver::new().unwrap().query(dname.as_bytes(), Class::IN, RecordType::SRV) {
+        if let Some(record) = res.answers::<SRV>().next() {
+            log::info!("SRV record found: {}:{} -> {}:{}", host, port, record.data.name, record.data.port);
+            *host = record.data.name;
+            *port = record.data.port;
+        }
+    }
+}
+
+async fn ping(handshake: &[u8], host: &str, port: &u16, timeout: Duration) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    future::timeout(timeout, ping_java(handshake, host, port)).await?
+}
+
+async fn ping_java(handshake: &[u8], host: &str, port: &u16) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    log::debug!("connecting to: {}:{}", host, port);
+    let start = Instant::now();
+    let mut stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+    let latency = start.elapsed().as_millis() as u64;
+
+    log::debug!("writing handshake {:?}", handshake);
+    stream.write_all(handshake).await?;
+
+    let mut status = request_status(&mut stream).await?;
+    status.latency = latency;
+
+    stream.write(&Vec::<u8>::new()).await?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(status)
+}
+
+const REQUEST: [u8; 2] = [1, 0];
+
+async fn request_status(stream: &mut TcpStream) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    log::debug!("writing request");
+    stream.write_all(&REQUEST).await?;
+    log::debug!("reading length");
+    let length = from_var_int(stream).await?;
+    log::debug!("length {}", length);
+    if length > 0 {
+        let prefix = from_var_int(stream).await?;
+        log::debug!("string prefix: {}", prefix);
+
+        let string_length = from_var_int(stream).await?;
+        log::debug!("string length: {}", string_length);
+
+        let mut buf = vec![0u8; string_length as usize];
+        stream.read(&mut buf).await?;
+        log::debug!("read status ({} bytes)\n{}", string_length, String::from_utf8_lossy(&buf));
+        Ok(serde_json::from_slice::<Status>(&buf)?)
+    } else {
+        Err(InternalError::new("invalid status length").into())
+    }
+}
+
+fn handshake(host: &str, port: &u16) -> Vec<u8> {
+    let host = host.to_owned() + "\0FML3\0";
+    let mut data = to_var_int(-1); // Protocol Number
+    data.extend(to_var_int(host.len() as i32)); // Host length
+    data.extend(host.bytes()); // Host
+    data.push((port & 0x00FF) as u8); // Port lower
+    data.push((port >> 8) as u8); // Port upper
+    data.push(1); // Next state
+    let mut handshake = to_var_int(data.len() as i32 + 1); // Packet length
+    handshake.push(0); // Packet ID
+    handshake.extend(data);
+    handshake
+}
+
+const SEGMENT: u32 = 0x7F;
+const CONTINUE: u32 = 0x80;
+
+fn to_var_int(input: i32) -> Vec<u8> {
+    let mut input = input as u32;
+    let mut data = Vec::<u8>::new();
+    loop {
+        if input & !SEGMENT == 0 {
+            data.push(input as u8);
+            break;
+        }
+        data.push(((input & SEGMENT) | CONTINUE) as u8);
+        input >>= 7;
+    }
+    data
+}
+
+async fn from_var_int(input: &mut TcpStream) -> Result<i32, Box<dyn Error + Send + Sync>> {
+    let mut result = 0;
+    let mut i = 0;
+    loop {
+        let mut buf = vec![0u8; 1];
+        if input.read(&mut buf).await? == 1 {
+            let byte = buf[0];
+            result |= (((byte as u32) & SEGMENT) as i32) << i;
+            if byte & (CONTINUE as u8) == 0 {
+                break
+            }
+        }
+        i += 7;
+        if i >= 32 {
+            return Err(InternalError::new("varint too long").into());
+        }
+    }
+    Ok(result)
+}
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1C;
+const MAGIC: [u8; 16] = [0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78];
+
+async fn ping_bedrock(host: &str, port: &u16, timeout: Duration) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    future::timeout(timeout, unconnected_ping(host, port)).await?
+}
+
+async fn unconnected_ping(host: &str, port: &u16) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    log::debug!("sending unconnected ping to: {}:{}", host, port);
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(format!("{}:{}", host, port)).await?;
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let mut packet = vec![UNCONNECTED_PING];
+    packet.extend(time.to_be_bytes()); // Client time
+    packet.extend(MAGIC);
+    packet.extend(0_u64.to_be_bytes()); // Client GUID
+    let start = Instant::now();
+    socket.send(&packet).await?;
+
+    let mut buf = vec![0u8; 1500];
+    let length = socket.recv(&mut buf).await?;
+    let latency = start.elapsed().as_millis() as u64;
+    // Packet ID, time, server GUID, magic, string length
+    if length < 35 || buf[0] != UNCONNECTED_PONG {
+        return Err(InternalError::new("invalid unconnected pong").into());
+    }
+    let string_length = u16::from_be_bytes([buf[33], buf[34]]) as usize;
+    if length < 35 + string_length {
+        return Err(InternalError::new("invalid server id length").into());
+    }
+    let data = String::from_utf8_lossy(&buf[35..35 + string_length]);
+    log::debug!("read server id ({} bytes)\n{}", string_length, data);
+    let mut status = parse_server_id(&data)?;
+    status.latency = latency;
+    Ok(status)
+}
+
+// Edition;MOTD line 1;Protocol;Version;Online;Max;Server ID;MOTD line 2;Game mode;...
+fn parse_server_id(data: &str) -> Result<Status, Box<dyn Error + Send + Sync>> {
+    let fields = data.split(';').collect::<Vec<_>>();
+    if fields.len() < 6 {
+        return Err(InternalError::new("invalid server id").into());
+    }
+    let mut text = fields[1].to_owned();
+    if let Some(line) = fields.get(7).filter(|line| !line.is_empty()) {
+        text.push('\n');
+        text.push_str(line);
+    }
+    Ok(Status {
+        version: Version {
+            name: fields[3].to_owned(),
+            protocol: fields[2].parse()?,
+        },
+        players: Players {
+            online: fields[4].parse()?,
+            max: fields[5].parse()?,
+            sample: None,
+        },
+        description: Description { text },
+        favicon: None,
+        enforces_secure_chat: false,
+        previews_chat: false,
+        host: String::new(),
+        port: 0,
+        latency: 0,
+        response_time: 0,
+        target: Default::default(),
+    })
+}
@@ -0,0 +1,281 @@
+use std::{
+    error::Error,
+    sync::Arc,
+    time::Duration,
+};
+use async_std::task;
+use chrono::{Datelike, Timelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    chart::{self, Chart},
+    config::Target,
+    history::{self, HistoryConfig, Summary},
+    models::{InternalError, Status},
+    notify::{self, TimeSettings},
+    state,
+    template::Context,
+    time,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportConfig {
+    // Name of the report used in logs and the "%report" placeholder
+    pub name: String,
+
+    // Cron expression of the times the report is sent, in the time zone of the report
+    pub schedule: Schedule,
+
+    // IANA time zone the schedule is evaluated in, e.g. "Europe/Warsaw". Defaults to "UTC"
+    #[serde(default = "notify::default_timezone", deserialize_with = "notify::deserialize_timezone")]
+    pub timezone: Tz,
+
+    // Time (in seconds) before the report covered by it. Defaults to a day.
+    // Whole days cover calendar days of the time zone, which last 23 or 25 hours when the clocks change
+    #[serde(default = "default_period")]
+    pub period: u64,
+
+    // Names of the targets reported on. Defaults to every target
+    #[serde(default)]
+    pub targets: Vec<String>,
+
+    // Names of the notifier instances the report is sent to.
+    // Defaults to the instances listing the "report" event
+    #[serde(default)]
+    pub notifiers: Vec<String>,
+
+    // Whether a chart of the number of players is attached to the report
+    #[serde(default)]
+    pub chart: bool,
+}
+
+fn default_period() -> u64 {
+    24 * 60 * 60
+}
+
+// Aggregates of the history of a target sent by a report
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub name: String,
+    // Unix times of the period covered by the report
+    pub from: u64,
+    pub to: u64,
+    #[serde(flatten)]
+    pub summary: Summary,
+    // PNG chart of the number of players during the period, serialized as base64
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_chart")]
+    pub chart: Option<Vec<u8>>,
+}
+
+fn serialize_chart<S: Serializer>(chart: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match chart {
+        Some(chart) => serializer.serialize_str(&base64::encode(chart)),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl Report {
+    // Adds the variables of the report to the context of the templates, with the peak in the time zone and formats of the notifier
+    pub fn extend(&self, context: &mut Context, settings: &TimeSettings) {
+        context.insert("report", self.name.as_str());
+        context.insert("peak_time", self.summary.peak_time.map(|time| settings.time(time)).unwrap_or_default());
+        context.insert("peak_date", self.summary.peak_time.map(|time| settings.date(time)).unwrap_or_default());
+        context.insert("peak", self.summary.peak);
+        // Rounded as text, which still compares as a number
+        context.insert("uptime_percent", format!("{:.1}", self.summary.uptime_percent));
+        context.insert("unique_players", self.summary.unique_players);
+        context.insert("avg_online", format!("{:.1}", self.summary.avg_online));
+    }
+}
+
+// Checks the schedules every minute and sends the reports that are due
+pub fn start(reports: Vec<ReportConfig>, history: &HistoryConfig, targets: &[Target]) -> Result<(), Box<dyn Error>> {
+    if reports.is_empty() {
+        return Ok(());
+    }
+    for report in reports.iter() {
+        if let Some(unknown) = report.targets.iter().find(|name| !targets.iter().any(|target| &target.name == *name)) {
+            return Err(InternalError::new(format!("report '{}' references unknown target '{}'", report.name, unknown)).into());
+        }
+    }
+    let history = history.clone();
+    let targets = targets.iter().cloned().map(Arc::new).collect::<Vec<_>>();
+    task::spawn(async move {
+        let mut last_minute = state::now() / 60;
+        loop {
+            task::sleep(Duration::from_secs(60 - state::now() % 60)).await;
+            let minute = state::now() / 60;
+            if minute == last_minute {
+                continue;
+            }
+            last_minute = minute;
+            for report in reports.iter().filter(|report| report.schedule.matches(minute * 60, report.timezone)) {
+                log::info!("sending the {} report", report.name);
+                // The history is read and the chart drawn on a blocking thread
+                let (report, history, targets) = (report.clone(), history.clone(), targets.clone());
+                task::spawn_blocking(move || send(&report, &history, &targets, minute * 60)).await;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn send(report: &ReportConfig, history: &HistoryConfig, targets: &[Arc<Target>], now: u64) {
+    let from = match report.period % 86400 {
+        0 => time::days_before(now, report.period / 86400, report.timezone),
+        _ => now.saturating_sub(report.period),
+    };
+    for target in targets.iter().filter(|target| report.targets.is_empty() || report.targets.contains(&target.name)) {
+        let summary = match history::query(history, &target.name, from, now, None) {
+            Ok(history) => history.summary,
+            Err(err) => {
+                log::error!("failed to read the history of {} for the {} report: {}", target.name, report.name, err);
+                continue;
+            },
+        };
+        let chart = match report.chart {
+            true => match Chart::from_history(history, &[&target.name], from, now, chart::DEFAULT_WIDTH) {
+                Ok(chart) => Some(chart.png(chart::DEFAULT_WIDTH, chart::DEFAULT_HEIGHT)),
+                Err(err) => {
+                    log::error!("failed to draw the chart of {} for the {} report: {}", target.name, report.name, err);
+                    None
+                },
+            },
+            false => None,
+        };
+        let status = state::get(&target.name)
+            .and_then(|state| state.status)
+            .unwrap_or_else(|| Arc::new(Status::offline(target.host.clone(), target.port(), target.clone())));
+        notify::report(Report { name: report.name.clone(), from, to: now, summary, chart }, status, &report.notifiers);
+    }
+}
+
+// Cron expression: minute, hour, day of month, month and day of week (0 or 7 is Sunday)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    // Whether the day fields were restricted, in which case matching either of them is enough
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self, InternalError> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(InternalError::new(format!("schedule '{}' must have 5 fields", expression)));
+        }
+        let mut weekdays = field(fields[4], 0, 7)?;
+        // Sunday is both 0 and 7
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+        Ok(Self {
+            minutes: field(fields[0], 0, 59)?,
+            hours: field(fields[1], 0, 23)?,
+            days: field(fields[2], 1, 31)?,
+            months: field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    // Whether the schedule matches the minute of the Unix time in the time zone
+    pub fn matches(&self, time: u64, timezone: Tz) -> bool {
+        let local = time::local(time, timezone);
+        let (day, weekday) = (local.day() as usize, local.weekday().num_days_from_sunday() as usize);
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => self.days[day] || self.weekdays[weekday],
+            _ => self.days[day] && self.weekdays[weekday],
+        };
+        self.minutes[local.minute() as usize] && self.hours[local.hour() as usize] && self.months[local.month() as usize] && day_matches
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = InternalError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+// Values allowed by a field, indexed by the value
+fn field(field: &str, min: usize, max: usize) -> Result<Vec<bool>, InternalError> {
+    let invalid = || InternalError::new(format!("'{}' is not a valid schedule field", field));
+    let mut allowed = vec![false; max + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse().map_err(|_| invalid())?, end.parse().map_err(|_| invalid())?),
+                None => {
+                    let start = range.parse().map_err(|_| invalid())?;
+                    (start, if part.contains('/') { max } else { start })
+                },
+            },
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step) {
+            allowed[value] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_schedules() {
+        assert!(Schedule::parse("0 0 * * *").is_ok());
+        assert!(Schedule::parse("*/15 8-18 * * 1-5").is_ok());
+        assert!(Schedule::parse("0 12 1,15 * *").is_ok());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("0 0 * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert_eq!(Schedule::parse("@daily").unwrap(), Schedule::parse("0 0 * * *").unwrap());
+    }
+
+    #[test]
+    fn matches_times() {
+        // 2024-05-01 18:30 UTC, a Wednesday
+        let time = 1714588200;
+        assert!(Schedule::parse("30 18 * * *").unwrap().matches(time, Tz::UTC));
+        assert!(Schedule::parse("*/10 * * * 3").unwrap().matches(time, Tz::UTC));
+        assert!(Schedule::parse("30 18 1 5 *").unwrap().matches(time, Tz::UTC));
+        assert!(!Schedule::parse("0 0 * * *").unwrap().matches(time, Tz::UTC));
+        assert!(!Schedule::parse("30 18 * * 0").unwrap().matches(time, Tz::UTC));
+        // Either of the restricted day fields is enough
+        assert!(Schedule::parse("30 18 15 * 3").unwrap().matches(time, Tz::UTC));
+        assert!(Schedule::parse("30 18 * * 7").unwrap().matches(time + 4 * 86400, Tz::UTC));
+        // 20:30 in Warsaw
+        let warsaw = "Europe/Warsaw".parse().unwrap();
+        assert!(Schedule::parse("30 20 * * *").unwrap().matches(time, warsaw));
+        assert!(!Schedule::parse("30 18 * * *").unwrap().matches(time, warsaw));
+        // Midnight of 2024-05-02 in Warsaw, while still the 1st in UTC
+        assert!(Schedule::parse("0 0 2 5 *").unwrap().matches(time + 12600, warsaw));
+    }
+}
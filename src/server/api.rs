@@ -0,0 +1,112 @@
+use async_std::task;
+use http_types::{Request, StatusCode};
+
+use crate::{
+    badge,
+    chart::{self, Chart},
+    history,
+    state,
+    time,
+};
+use super::Body;
+
+// Period returned when the from parameter is not set
+const DEFAULT_HISTORY_PERIOD: u64 = 24 * 60 * 60;
+
+pub fn targets() -> http_types::Result<Body> {
+    Body::json(&state::all())
+}
+
+pub fn target(name: &str) -> http_types::Result<Option<Body>> {
+    state::get(name).map(|state| Body::json(&state)).transpose()
+}
+
+pub fn players(name: &str) -> http_types::Result<Option<Body>> {
+    let state = match state::get(name) {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+    match state.status.as_ref() {
+        Some(status) => Ok(Some(Body::json(&status.players)?)),
+        None => Ok(Some(Body::json(&serde_json::json!({ "max": 0, "online": 0, "sample": null }))?)),
+    }
+}
+
+// Decodes the "data:image/png;base64,..." favicon sent by the server
+pub fn favicon(name: &str) -> http_types::Result<Option<Body>> {
+    let favicon = state::get(name)
+        .and_then(|state| state.status)
+        .and_then(|status| status.favicon.clone());
+    match favicon {
+        Some(favicon) => {
+            let data = favicon.split_once(',').map(|(_, data)| data).unwrap_or(&favicon);
+            let data = base64::decode(data.trim())?;
+            Ok(Some(Body::new(data, "image/png")))
+        },
+        None => Ok(None),
+    }
+}
+
+// Badge of the latest status of a target, available once it was pinged
+pub fn badge(name: &str) -> Option<Body> {
+    badge::get(name).map(|badge| Body::new(badge.as_bytes().to_vec(), "image/svg+xml"))
+}
+
+// Recorded history of a target, limited by the from, to and resolution query parameters
+pub async fn history(name: &str, request: &Request) -> http_types::Result<Option<Body>> {
+    let config = match history::config() {
+        Some(config) if state::get(name).is_some() => config,
+        _ => return Ok(None),
+    };
+    let (from, to) = period(request)?;
+    let resolution = parameter(request, "resolution").map(|resolution| time::parse_duration(&resolution)).transpose().map_err(bad_request)?;
+    let history = history::load(config, name, from, to, resolution).await.map_err(internal_error)?;
+    Body::json(&history).map(Some)
+}
+
+// Chart of the number of players of the targets over the period set by the from and to query parameters
+pub async fn chart<S: AsRef<str>>(names: &[S], request: &Request, png: bool) -> http_types::Result<Option<Body>> {
+    let config = match history::config() {
+        Some(config) if !names.is_empty() && names.iter().all(|name| state::get(name.as_ref()).is_some()) => config,
+        _ => return Ok(None),
+    };
+    let (from, to) = period(request)?;
+    let size = |key, default: u32| parameter(request, key)
+        .map(|value| value.parse::<u32>().map_err(|err| bad_request(err.to_string())))
+        .transpose()
+        .map(|value| value.unwrap_or(default).clamp(100, 4000));
+    let (width, height) = (size("width", chart::DEFAULT_WIDTH)?, size("height", chart::DEFAULT_HEIGHT)?);
+    let names = names.iter().map(|name| name.as_ref().to_owned()).collect::<Vec<_>>();
+    // Reading the history and drawing the chart run on a blocking thread
+    let body = task::spawn_blocking(move || {
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        let chart = Chart::from_history(config, &names, from, to, width).map_err(|err| err.to_string())?;
+        Ok::<_, String>(match png {
+            true => Body::new(chart.png(width, height), "image/png"),
+            false => Body::new(chart.svg(width, height).into_bytes(), "image/svg+xml"),
+        })
+    }).await.map_err(internal_error)?;
+    Ok(Some(body))
+}
+
+fn parameter(request: &Request, key: &str) -> Option<String> {
+    request.url().query_pairs().find(|(name, _)| name == key).map(|(_, value)| value.into_owned())
+}
+
+// Period set by the from and to query parameters
+fn period(request: &Request) -> http_types::Result<(u64, u64)> {
+    let to = parameter(request, "to").map(|to| time::parse_time(&to)).transpose().map_err(bad_request)?.unwrap_or_else(state::now);
+    let from = match parameter(request, "from") {
+        Some(from) => time::parse_time(&from).map_err(bad_request)?,
+        None => to.saturating_sub(DEFAULT_HISTORY_PERIOD),
+    };
+    Ok((from, to))
+}
+
+fn bad_request<E: std::fmt::Display + std::fmt::Debug + Send + Sync + 'static>(err: E) -> http_types::Error {
+    http_types::Error::from_str(StatusCode::BadRequest, err)
+}
+
+fn internal_error<E: std::fmt::Display>(err: E) -> http_types::Error {
+    http_types::Error::from_str(StatusCode::InternalServerError, err.to_string())
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    error::Error,
+    hash::{Hash, Hasher},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+use async_std::{
+    net::TcpListener,
+    task,
+};
+use futures_util::{StreamExt, TryStreamExt};
+use http_types::{
+    headers,
+    Method,
+    Mime,
+    Request,
+    Response,
+    StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+
+use crate::{events, metrics, state};
+
+mod api;
+
+#[derive(Debug, Deserialize)]
+pub struct ServerConfig {
+    // Address the embedded HTTP server listens on, e.g. "127.0.0.1:9100"
+    pub listen: String,
+
+    // Value of the Access-Control-Allow-Origin header. Defaults to "*"
+    #[serde(default = "default_cors_origin")]
+    pub cors_origin: String,
+}
+
+fn default_cors_origin() -> String {
+    "*".to_owned()
+}
+
+// Single-page dashboard served at the root, using the other endpoints
+const DASHBOARD: &str = include_str!("../../assets/dashboard.html");
+
+// Interval of the comments keeping idle event streams open
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+// Binds the listener and serves requests in the background
+pub async fn start(config: &ServerConfig) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(&config.listen).await?;
+    log::info!("listening on http://{}", listener.local_addr()?);
+    let cors_origin: Arc<str> = config.cors_origin.as_str().into();
+    task::spawn(async move {
+        let mut incoming = listener.incoming();
+        while let Some(stream) = incoming.next().await {
+            match stream {
+                Ok(stream) => {
+                    let cors_origin = cors_origin.clone();
+                    task::spawn(async move {
+                        let handler = |request| handle(request, cors_origin.clone());
+                        if let Err(err) = async_h1::accept(stream, handler).await {
+                            log::debug!("failed to handle connection: {}", err);
+                        }
+                    });
+                },
+                Err(err) => log::error!("failed to accept connection: {}", err),
+            }
+        }
+    });
+    task::spawn(async {
+        loop {
+            task::sleep(HEARTBEAT).await;
+            events::heartbeat();
+        }
+    });
+    Ok(())
+}
+
+async fn handle(request: Request, cors_origin: Arc<str>) -> http_types::Result<Response> {
+    log::debug!("{} {}", request.method(), request.url().path());
+    let mut response = match request.method() {
+        Method::Get | Method::Head => route(&request).await.unwrap_or_else(|err| {
+            let mut response = Response::new(err.status());
+            response.set_body(err.to_string());
+            response
+        }),
+        Method::Options => {
+            let mut response = Response::new(StatusCode::NoContent);
+            response.insert_header(headers::ACCESS_CONTROL_ALLOW_METHODS, "GET, HEAD, OPTIONS");
+            response.insert_header(headers::ACCESS_CONTROL_ALLOW_HEADERS, "If-None-Match, Last-Event-ID");
+            response
+        },
+        _ => Response::new(StatusCode::MethodNotAllowed),
+    };
+    response.insert_header(headers::ACCESS_CONTROL_ALLOW_ORIGIN, cors_origin.as_ref());
+    response.insert_header(headers::ACCESS_CONTROL_EXPOSE_HEADERS, "ETag");
+    Ok(response)
+}
+
+async fn route(request: &Request) -> http_types::Result<Response> {
+    let segments = request.url().path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+    if segments == ["events"] {
+        return Ok(stream(request));
+    }
+    let body = match segments.as_slice() {
+        [] => Some(Body::new(DASHBOARD.as_bytes().to_vec(), "text/html; charset=utf-8")),
+        ["events", "recent"] => Some(Body::json(&events::recent())?),
+        ["metrics"] => Some(Body::new(metrics::render().into_bytes(), "text/plain; version=0.0.4")),
+        ["targets"] => Some(api::targets()?),
+        ["targets", name] => api::target(name)?,
+        ["targets", name, "players"] => api::players(name)?,
+        ["targets", name, "favicon.png"] => api::favicon(name)?,
+        ["targets", name, "history"] => api::history(name, request).await?,
+        ["targets", name, "badge.svg"] => api::badge(name),
+        ["targets", name, "chart.svg"] => api::chart(&[name], request, false).await?,
+        ["targets", name, "chart.png"] => api::chart(&[name], request, true).await?,
+        ["chart.svg"] => api::chart(&targets(request), request, false).await?,
+        ["chart.png"] => api::chart(&targets(request), request, true).await?,
+        _ => None,
+    };
+    Ok(match body {
+        Some(body) => body.respond(request),
+        None => Response::new(StatusCode::NotFound),
+    })
+}
+
+// Targets named by the comma separated targets query parameter, every target when not set
+fn targets(request: &Request) -> Vec<String> {
+    match request.url().query_pairs().find(|(key, _)| key == "targets") {
+        Some((_, names)) => names.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_owned).collect(),
+        None => state::all().into_iter().map(|state| state.name).collect(),
+    }
+}
+
+// Streams the events as Server-Sent Events, replaying the ones missed since the Last-Event-ID
+fn stream(request: &Request) -> Response {
+    let last_id = request.header("Last-Event-ID")
+        .map(|values| values.last().as_str().to_owned())
+        .or_else(|| request.url().query_pairs().find(|(key, _)| key == "last_event_id").map(|(_, value)| value.into_owned()))
+        .and_then(|id| id.trim().parse::<u64>().ok());
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_content_type("text/event-stream".parse::<Mime>().unwrap());
+    response.insert_header(headers::CACHE_CONTROL, "no-cache");
+    if request.method() != Method::Head {
+        let reader = events::subscribe(last_id)
+            .map(|frame| Ok::<_, io::Error>(frame.as_bytes().to_vec()))
+            .into_async_read();
+        response.set_body(http_types::Body::from_reader(reader, None));
+    }
+    response
+}
+
+// Response body, sent with an ETag so unchanged responses can be revalidated
+struct Body {
+    data: Vec<u8>,
+    content_type: &'static str,
+}
+
+impl Body {
+    fn new(data: Vec<u8>, content_type: &'static str) -> Self {
+        Self { data, content_type }
+    }
+
+    fn json<T: serde::Serialize>(value: &T) -> http_types::Result<Self> {
+        Ok(Self::new(serde_json::to_vec(value)?, "application/json"))
+    }
+
+    fn respond(self, request: &Request) -> Response {
+        let mut hasher = DefaultHasher::new();
+        self.data.hash(&mut hasher);
+        let etag = format!("\"{:016x}\"", hasher.finish());
+        let matches = request.header(headers::IF_NONE_MATCH)
+            .map(|values| values.iter().any(|value| value.as_str().split(',').any(|tag| tag.trim() == etag || tag.trim() == "*")))
+            .unwrap_or(false);
+        let mut response = if matches {
+            Response::new(StatusCode::NotModified)
+        } else {
+            let mut response = Response::new(StatusCode::Ok);
+            if request.method() != Method::Head {
+                response.set_body(self.data);
+            }
+            response
+        };
+        if let Ok(mime) = self.content_type.parse::<Mime>() {
+            response.set_content_type(mime);
+        }
+        response.insert_header(headers::ETAG, etag);
+        response.insert_header(headers::CACHE_CONTROL, "no-cache");
+        response
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use async_std::channel::{self, Receiver, Sender};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::{
+    config::{Edition, Target},
+    models::Status,
+};
+
+// Latest known state of a target, as collected by the main loop
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetState {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub edition: Edition,
+    pub tags: Vec<String>,
+    // Whether the last ping succeeded
+    pub up: bool,
+    // Unix time of the last ping
+    pub last_checked: Option<u64>,
+    // Unix time of the last successful ping
+    pub last_success: Option<u64>,
+    // Error of the last ping, if it failed
+    pub error: Option<String>,
+    // Status received by the last successful ping
+    pub status: Option<Arc<Status>>,
+    // Whether the notifications about the target are muted
+    pub muted: bool,
+}
+
+static TARGETS: Lazy<RwLock<BTreeMap<String, TargetState>>> = Lazy::new(Default::default);
+
+// Channels waking up the monitors to ping their target right away
+static PING_REQUESTS: Lazy<Mutex<HashMap<String, Sender<()>>>> = Lazy::new(Default::default);
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+// Adds the target, returning the receiver of the requests to ping it right away
+pub fn register(target: &Target, host: &str, port: u16) -> Receiver<()> {
+    TARGETS.write().unwrap().insert(target.name.clone(), TargetState {
+        name: target.name.clone(),
+        host: host.to_owned(),
+        port,
+        edition: target.edition,
+        tags: target.tags.clone(),
+        up: false,
+        last_checked: None,
+        last_success: None,
+        error: None,
+        status: None,
+        muted: false,
+    });
+    let (sender, receiver) = channel::bounded(1);
+    PING_REQUESTS.lock().unwrap().insert(target.name.clone(), sender);
+    receiver
+}
+
+// Wakes up the monitor of the target. A request already waiting is enough
+pub fn request_ping(target: &str) {
+    if let Some(sender) = PING_REQUESTS.lock().unwrap().get(target) {
+        sender.try_send(()).ok();
+    }
+}
+
+pub fn set_muted(target: &str, muted: bool) {
+    if let Some(state) = TARGETS.write().unwrap().get_mut(target) {
+        state.muted = muted;
+    }
+}
+
+pub fn is_muted(target: &str) -> bool {
+    TARGETS.read().unwrap().get(target).is_some_and(|state| state.muted)
+}
+
+pub fn record_status(status: Arc<Status>) {
+    if let Some(state) = TARGETS.write().unwrap().get_mut(&status.target.name) {
+        let now = now();
+        state.up = true;
+        state.last_checked = Some(now);
+        state.last_success = Some(now);
+        state.error = None;
+        state.status = Some(status);
+    }
+}
+
+pub fn record_error(target: &str, error: String) {
+    if let Some(state) = TARGETS.write().unwrap().get_mut(target) {
+        state.up = false;
+        state.last_checked = Some(now());
+        state.error = Some(error);
+    }
+}
+
+pub fn get(target: &str) -> Option<TargetState> {
+    TARGETS.read().unwrap().get(target).cloned()
+}
+
+pub fn all() -> Vec<TargetState> {
+    TARGETS.read().unwrap().values().cloned().collect()
+}
@@ -0,0 +1,837 @@
+// Template language of the notification messages. Text may contain "{{ expression }}" outputs,
+// "{% if %}" and "{% for %}" blocks, "{# comments #}", "{% raw %}" blocks kept as is, and the
+// "%name" placeholders of the original syntax, "%%" being a literal "%". The values written are
+// escaped for the service the text is sent to, while the text of the template is written as is
+
+use std::{cmp::Ordering, collections::HashMap};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::{map::Map, Value};
+
+use crate::models::InternalError;
+
+// Variables available to a template
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    values: Map<String, Value>,
+    // Separator of the lists written without the join filter, e.g. the players
+    separator: String,
+    // Lists written through templates when the variable is written as is
+    formatted: HashMap<String, Formatted>,
+}
+
+// List written through a template for every item, as "%players" is
+#[derive(Debug, Clone)]
+pub struct Formatted {
+    pub items: Vec<Context>,
+    pub template: Template,
+    pub separator: String,
+    // Template and variables written after the items, e.g. the number of players left out
+    pub suffix: Option<(Template, Context)>,
+}
+
+impl Context {
+    pub fn new<S: AsRef<str>>(separator: S) -> Self {
+        Self { values: Map::new(), separator: separator.as_ref().to_owned(), formatted: HashMap::new() }
+    }
+
+    pub fn insert<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.values.insert(name.to_owned(), value.into());
+    }
+
+    // Adds a list which filters and loops read as the value, while writing it uses the formatted items
+    pub fn insert_formatted<V: Into<Value>>(&mut self, name: &str, value: V, formatted: Formatted) {
+        self.insert(name, value);
+        self.formatted.insert(name.to_owned(), formatted);
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    // Text of a raw block, without placeholders
+    Raw(String),
+    Output(Expression),
+    // Conditions with their bodies, followed by the else body
+    If(Vec<(Expression, Vec<Node>)>, Vec<Node>),
+    For { name: String, list: Expression, body: Vec<Node>, otherwise: Vec<Node> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Literal(Value),
+    // Variable followed by the names of its fields
+    Variable(Vec<String>),
+    Filter(Box<Expression>, String, Vec<Expression>),
+    Compare(Box<Expression>, Operator, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Escaping of the values written into the text of a service. The variants are only constructed by
+// the notifiers compiled in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(all(feature = "discord", feature = "slack", feature = "custom")), allow(dead_code))]
+pub enum Escape {
+    None,
+    // Discord markdown, with the mentions broken by a zero-width space
+    Discord,
+    // Slack control characters as HTML entities
+    Slack,
+    // HTTP header values, without line breaks and control characters
+    Header,
+}
+
+impl Escape {
+    // Escapes the value written after the output rendered so far
+    fn apply(self, value: &str, output: &str) -> String {
+        match self {
+            Escape::None => value.to_owned(),
+            // Markdown isn't parsed inside code, where only a backtick could end it
+            Escape::Discord if in_code(output) => value.replace('`', "\u{2cb}"),
+            Escape::Discord => {
+                let mut escaped = String::with_capacity(value.len());
+                let mut line_start = output.is_empty() || output.ends_with('\n');
+                for c in value.chars() {
+                    match c {
+                        // Headings, lists and quotes only start lines, while links need brackets
+                        '\\' | '*' | '_' | '~' | '`' | '|' | '<' | '[' | ']' => {
+                            escaped.push('\\');
+                            escaped.push(c);
+                        },
+                        '#' | '-' | '>' if line_start => {
+                            escaped.push('\\');
+                            escaped.push(c);
+                        },
+                        '@' => escaped.push_str("@\u{200b}"),
+                        c => escaped.push(c),
+                    }
+                    line_start = c == '\n' || (line_start && c == ' ');
+                }
+                escaped
+            },
+            Escape::Slack => value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+            Escape::Header => value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect(),
+        }
+    }
+}
+
+// Whether the output ends inside a Discord code block or inline code, counting the backticks not escaped
+fn in_code(output: &str) -> bool {
+    let (mut block, mut inline) = (false, false);
+    let mut rest = output;
+    let mut escaped = false;
+    while let Some(c) = rest.chars().next() {
+        if !escaped && rest.starts_with("```") && !inline {
+            block = !block;
+            rest = &rest[3..];
+            continue;
+        }
+        if !escaped && c == '`' && !block {
+            inline = !inline;
+        }
+        escaped = !escaped && c == '\\';
+        rest = &rest[c.len_utf8()..];
+    }
+    block || inline
+}
+
+const FILTERS: [&str; 6] = ["upper", "lower", "truncate", "join", "default", "length"];
+
+// Renders the input, only replacing the "%name" placeholders when it isn't a valid template,
+// as a message of the original syntax may contain "{{", "{%" or "{#" by chance
+pub fn render(input: &str, context: &Context, escape: Escape) -> String {
+    match Template::parse(input) {
+        Ok(template) => template.render(context, escape),
+        Err(err) => {
+            log::warn!("invalid template '{}', only replacing the placeholders: {}", input, err);
+            Template { nodes: vec![Node::Text(input.to_owned())] }.render(context, escape)
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self, InternalError> {
+        let mut segments = segments(input)?.into_iter().peekable();
+        let (nodes, end) = parse_nodes(&mut segments)?;
+        match end {
+            None => Ok(Self { nodes }),
+            Some(tag) => Err(InternalError::new(format!("unexpected '{{% {} %}}'", tag))),
+        }
+    }
+
+    pub fn render(&self, context: &Context, escape: Escape) -> String {
+        let mut output = String::new();
+        self.render_into(context, escape, &mut output);
+        output
+    }
+
+    // Appends to the output, so the escaping knows what was written before
+    fn render_into(&self, context: &Context, escape: Escape, output: &mut String) {
+        render_nodes(&self.nodes, &Renderer { context, escape }, &mut Vec::new(), output);
+    }
+}
+
+// Templates are deserialized from their text, failing on invalid ones
+impl<'de> Deserialize<'de> for Template {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Template::parse(&text).map_err(|err| de::Error::custom(format!("invalid template '{}': {}", text, err)))
+    }
+}
+
+// Parts of a template between the tags
+#[derive(Debug)]
+enum Segment<'a> {
+    Text(&'a str),
+    Raw(&'a str),
+    Output(&'a str),
+    Tag(&'a str),
+}
+
+fn segments(input: &str) -> Result<Vec<Segment<'_>>, InternalError> {
+    let mut segments = Vec::new();
+    let mut rest = input;
+    while let Some(start) = ["{{", "{%", "{#"].iter().filter_map(|open| rest.find(open)).min() {
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let close = match &rest[start..start + 2] {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let end = rest[start + 2..].find(close)
+            .map(|end| start + 2 + end)
+            .ok_or_else(|| InternalError::new(format!("unclosed '{}'", &rest[start..start + 2])))?;
+        let inner = rest[start + 2..end].trim();
+        rest = &rest[end + 2..];
+        match close {
+            "}}" => segments.push(Segment::Output(inner)),
+            "%}" if inner == "raw" => {
+                let (raw, after) = find_endraw(rest).ok_or_else(|| InternalError::new("unclosed '{% raw %}'"))?;
+                segments.push(Segment::Raw(raw));
+                rest = after;
+            },
+            "%}" => segments.push(Segment::Tag(inner)),
+            _ => {},
+        }
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+// Text of a raw block and the rest of the input after its end tag
+fn find_endraw(input: &str) -> Option<(&str, &str)> {
+    let mut offset = 0;
+    while let Some(start) = input[offset..].find("{%").map(|start| offset + start) {
+        let end = input[start..].find("%}").map(|end| start + end)?;
+        if input[start + 2..end].trim() == "endraw" {
+            return Some((&input[..start], &input[end + 2..]));
+        }
+        offset = end;
+    }
+    None
+}
+
+type Segments<'a> = std::iter::Peekable<std::vec::IntoIter<Segment<'a>>>;
+
+// Parses nodes until a tag ending the block, which is returned
+fn parse_nodes<'a>(segments: &mut Segments<'a>) -> Result<(Vec<Node>, Option<&'a str>), InternalError> {
+    let mut nodes = Vec::new();
+    while let Some(segment) = segments.next() {
+        match segment {
+            Segment::Text(text) => nodes.push(Node::Text(text.to_owned())),
+            Segment::Raw(text) => nodes.push(Node::Raw(text.to_owned())),
+            Segment::Output(expression) => nodes.push(Node::Output(parse_expression(expression)?)),
+            Segment::Tag(tag) => {
+                let (keyword, arguments) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                match keyword {
+                    "if" => nodes.push(parse_if(parse_expression(arguments)?, segments)?),
+                    "for" => nodes.push(parse_for(arguments, segments)?),
+                    "elif" | "else" | "endif" | "endfor" => return Ok((nodes, Some(tag))),
+                    _ => return Err(InternalError::new(format!("unknown tag '{{% {} %}}'", tag))),
+                }
+            },
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn parse_if(condition: Expression, segments: &mut Segments) -> Result<Node, InternalError> {
+    let mut branches = Vec::new();
+    let mut condition = Some(condition);
+    loop {
+        let (body, end) = parse_nodes(segments)?;
+        let end = end.ok_or_else(|| InternalError::new("missing '{% endif %}'"))?;
+        match condition.take() {
+            Some(condition) => branches.push((condition, body)),
+            // The body of the else branch
+            None if end == "endif" => return Ok(Node::If(branches, body)),
+            None => return Err(InternalError::new(format!("unexpected '{{% {} %}}' after '{{% else %}}'", end))),
+        }
+        match end.split_once(char::is_whitespace).unwrap_or((end, "")) {
+            ("elif", expression) => condition = Some(parse_expression(expression)?),
+            ("else", "") => {},
+            ("endif", "") => return Ok(Node::If(branches, Vec::new())),
+            _ => return Err(InternalError::new(format!("unexpected '{{% {} %}}' in '{{% if %}}'", end))),
+        }
+    }
+}
+
+fn parse_for(arguments: &str, segments: &mut Segments) -> Result<Node, InternalError> {
+    let invalid = || InternalError::new(format!("'{{% for {} %}}' must be '{{% for <name> in <list> %}}'", arguments));
+    let (name, list) = arguments.split_once(" in ").ok_or_else(invalid)?;
+    let name = name.trim();
+    if !is_identifier(name) {
+        return Err(invalid());
+    }
+    let list = parse_expression(list)?;
+    let (body, end) = parse_nodes(segments)?;
+    let otherwise = match end {
+        Some("endfor") => Vec::new(),
+        Some("else") => match parse_nodes(segments)? {
+            (otherwise, Some("endfor")) => otherwise,
+            _ => return Err(InternalError::new("missing '{% endfor %}'")),
+        },
+        _ => return Err(InternalError::new("missing '{% endfor %}'")),
+    };
+    Ok(Node::For { name: name.to_owned(), list, body, otherwise })
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Literal(Value),
+    Symbol(&'static str),
+}
+
+fn tokens(input: &str) -> Result<Vec<Token>, InternalError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {},
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => text.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, end)) if end == c => break,
+                        Some((_, c)) => text.push(c),
+                        None => return Err(InternalError::new(format!("unclosed string in '{}'", input))),
+                    }
+                }
+                tokens.push(Token::Literal(Value::String(text)));
+            },
+            '0'..='9' | '-' => {
+                let mut end = index + c.len_utf8();
+                while let Some((next, c)) = chars.peek().copied().filter(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    end = next + c.len_utf8();
+                    chars.next();
+                }
+                let number = input[index..end].parse::<f64>()
+                    .map_err(|_| InternalError::new(format!("invalid number '{}'", &input[index..end])))?;
+                tokens.push(Token::Literal(number_value(number)));
+            },
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = index + 1;
+                while let Some((next, _)) = chars.peek().copied().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                    end = next + 1;
+                    chars.next();
+                }
+                tokens.push(match &input[index..end] {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" | "none" => Token::Literal(Value::Null),
+                    identifier => Token::Identifier(identifier.to_owned()),
+                });
+            },
+            _ => {
+                let next = chars.peek().map(|(_, c)| *c);
+                let symbol = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('|', _) => "|",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (',', _) => ",",
+                    ('.', _) => ".",
+                    _ => return Err(InternalError::new(format!("unexpected '{}' in '{}'", c, input))),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Symbol(symbol));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_expression(input: &str) -> Result<Expression, InternalError> {
+    let tokens = tokens(input)?;
+    let mut parser = Parser { tokens: &tokens, position: 0 };
+    let expression = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(expression),
+        Some(_) => Err(InternalError::new(format!("unexpected input in '{}'", input))),
+    }
+}
+
+// Recursive descent parser of the expressions, from the lowest precedence
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Identifier(identifier)) if identifier == keyword);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Symbol(next)) if *next == symbol);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Expression, InternalError> {
+        let mut left = self.and()?;
+        while self.keyword("or") {
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, InternalError> {
+        let mut left = self.not()?;
+        while self.keyword("and") {
+            left = Expression::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expression, InternalError> {
+        match self.keyword("not") {
+            true => Ok(Expression::Not(Box::new(self.not()?))),
+            false => self.compare(),
+        }
+    }
+
+    fn compare(&mut self) -> Result<Expression, InternalError> {
+        let left = self.filtered()?;
+        let operator = match self.peek() {
+            Some(Token::Symbol("==")) => Operator::Equal,
+            Some(Token::Symbol("!=")) => Operator::NotEqual,
+            Some(Token::Symbol("<")) => Operator::Less,
+            Some(Token::Symbol("<=")) => Operator::LessOrEqual,
+            Some(Token::Symbol(">")) => Operator::Greater,
+            Some(Token::Symbol(">=")) => Operator::GreaterOrEqual,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+        Ok(Expression::Compare(Box::new(left), operator, Box::new(self.filtered()?)))
+    }
+
+    fn filtered(&mut self) -> Result<Expression, InternalError> {
+        let mut expression = self.primary()?;
+        while self.symbol("|") {
+            let name = match self.next() {
+                Some(Token::Identifier(name)) if FILTERS.contains(&name.as_str()) => name.clone(),
+                Some(Token::Identifier(name)) => return Err(InternalError::new(format!("unknown filter '{}'", name))),
+                _ => return Err(InternalError::new("missing filter name after '|'")),
+            };
+            let mut arguments = Vec::new();
+            if self.symbol("(") && !self.symbol(")") {
+                loop {
+                    arguments.push(self.or()?);
+                    if self.symbol(")") {
+                        break;
+                    }
+                    if !self.symbol(",") {
+                        return Err(InternalError::new(format!("missing ')' after the arguments of '{}'", name)));
+                    }
+                }
+            }
+            expression = Expression::Filter(Box::new(expression), name, arguments);
+        }
+        Ok(expression)
+    }
+
+    fn primary(&mut self) -> Result<Expression, InternalError> {
+        match self.next().cloned() {
+            Some(Token::Literal(value)) => Ok(Expression::Literal(value)),
+            Some(Token::Identifier(name)) => {
+                let mut path = vec![name];
+                while self.symbol(".") {
+                    match self.next() {
+                        Some(Token::Identifier(field)) => path.push(field.clone()),
+                        _ => return Err(InternalError::new("missing field name after '.'")),
+                    }
+                }
+                Ok(Expression::Variable(path))
+            },
+            Some(Token::Symbol("(")) => {
+                let expression = self.or()?;
+                match self.symbol(")") {
+                    true => Ok(expression),
+                    false => Err(InternalError::new("missing ')'")),
+                }
+            },
+            _ => Err(InternalError::new("missing value")),
+        }
+    }
+}
+
+// Variables of the loops being rendered, innermost last
+type Scope = Vec<(String, Value)>;
+
+struct Renderer<'a> {
+    context: &'a Context,
+    escape: Escape,
+}
+
+impl Renderer<'_> {
+    fn write(&self, value: &Value, output: &mut String) {
+        let text = self.escape.apply(&display(value, &self.context.separator), output);
+        output.push_str(&text);
+    }
+
+    // Writes a variable of the context, through its templates if it is formatted
+    fn write_variable(&self, name: &str, value: &Value, output: &mut String) {
+        let formatted = match self.context.formatted.get(name) {
+            Some(formatted) => formatted,
+            None => return self.write(value, output),
+        };
+        for (index, item) in formatted.items.iter().enumerate() {
+            if index > 0 {
+                output.push_str(&formatted.separator);
+            }
+            formatted.template.render_into(item, self.escape, output);
+        }
+        if let Some((template, context)) = formatted.suffix.as_ref() {
+            template.render_into(context, self.escape, output);
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], renderer: &Renderer, scope: &mut Scope, output: &mut String) {
+    let context = renderer.context;
+    for node in nodes {
+        match node {
+            Node::Text(text) => placeholders(text, renderer, output),
+            Node::Raw(text) => output.push_str(text),
+            // Variables of the context written as they are, rather than those of loops
+            Node::Output(Expression::Variable(path)) if path.len() == 1 && !scope.iter().any(|(name, _)| *name == path[0]) => {
+                renderer.write_variable(&path[0], context.get(&path[0]).unwrap_or(&Value::Null), output)
+            },
+            Node::Output(expression) => renderer.write(&evaluate(expression, context, scope), output),
+            Node::If(branches, otherwise) => {
+                let body = branches.iter()
+                    .find(|(condition, _)| truthy(&evaluate(condition, context, scope)))
+                    .map(|(_, body)| body)
+                    .unwrap_or(otherwise);
+                render_nodes(body, renderer, scope, output);
+            },
+            Node::For { name, list, body, otherwise } => {
+                let items = match evaluate(list, context, scope) {
+                    Value::Array(items) => items,
+                    Value::Null => Vec::new(),
+                    item => vec![item],
+                };
+                if items.is_empty() {
+                    render_nodes(otherwise, renderer, scope, output);
+                }
+                let count = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    let mut info = Map::new();
+                    info.insert("index".to_owned(), (index + 1).into());
+                    info.insert("first".to_owned(), (index == 0).into());
+                    info.insert("last".to_owned(), (index + 1 == count).into());
+                    scope.push(("loop".to_owned(), Value::Object(info)));
+                    scope.push((name.clone(), item));
+                    render_nodes(body, renderer, scope, output);
+                    scope.truncate(scope.len() - 2);
+                }
+            },
+        }
+    }
+}
+
+// Replaces the "%name" placeholders with the longest variable name matching
+fn placeholders(text: &str, renderer: &Renderer, output: &mut String) {
+    let context = renderer.context;
+    let mut rest = text;
+    while let Some(index) = rest.find('%') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('%') {
+            output.push('%');
+            rest = after;
+            continue;
+        }
+        let length = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        match (1..=length).rev().find_map(|length| context.get(&rest[..length]).map(|value| (length, value))) {
+            Some((length, value)) => {
+                renderer.write_variable(&rest[..length], value, output);
+                rest = &rest[length..];
+            },
+            None => output.push('%'),
+        }
+    }
+    output.push_str(rest);
+}
+
+fn evaluate(expression: &Expression, context: &Context, scope: &Scope) -> Value {
+    match expression {
+        Expression::Literal(value) => value.clone(),
+        Expression::Variable(path) => {
+            let root = scope.iter().rev()
+                .find(|(name, _)| *name == path[0])
+                .map(|(_, value)| value)
+                .or_else(|| context.get(&path[0]));
+            path[1..].iter()
+                .fold(root, |value, field| value.and_then(|value| value.get(field)))
+                .cloned()
+                .unwrap_or(Value::Null)
+        },
+        Expression::Filter(value, name, arguments) => {
+            let value = evaluate(value, context, scope);
+            let arguments = arguments.iter().map(|argument| evaluate(argument, context, scope)).collect::<Vec<_>>();
+            filter(value, name, &arguments, &context.separator)
+        },
+        Expression::Compare(left, operator, right) => {
+            let (left, right) = (evaluate(left, context, scope), evaluate(right, context, scope));
+            let ordering = compare(&left, &right, &context.separator);
+            Value::Bool(match operator {
+                Operator::Equal => ordering == Some(Ordering::Equal),
+                Operator::NotEqual => ordering != Some(Ordering::Equal),
+                Operator::Less => ordering == Some(Ordering::Less),
+                Operator::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                Operator::Greater => ordering == Some(Ordering::Greater),
+                Operator::GreaterOrEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            })
+        },
+        Expression::And(left, right) => Value::Bool(truthy(&evaluate(left, context, scope)) && truthy(&evaluate(right, context, scope))),
+        Expression::Or(left, right) => Value::Bool(truthy(&evaluate(left, context, scope)) || truthy(&evaluate(right, context, scope))),
+        Expression::Not(value) => Value::Bool(!truthy(&evaluate(value, context, scope))),
+    }
+}
+
+fn filter(value: Value, name: &str, arguments: &[Value], separator: &str) -> Value {
+    let argument = |index: usize| arguments.get(index).filter(|argument| !argument.is_null());
+    match name {
+        "upper" => Value::String(display(&value, separator).to_uppercase()),
+        "lower" => Value::String(display(&value, separator).to_lowercase()),
+        "truncate" => {
+            let text = display(&value, separator);
+            let length = argument(0).and_then(number).unwrap_or(255.0).max(0.0) as usize;
+            let suffix = argument(1).map(|suffix| display(suffix, separator)).unwrap_or_else(|| "...".to_owned());
+            match text.chars().count() > length {
+                true => Value::String(text.chars().take(length).collect::<String>() + &suffix),
+                false => Value::String(text),
+            }
+        },
+        "join" => {
+            let separator = argument(0).map(|separator| display(separator, "")).unwrap_or_else(|| separator.to_owned());
+            match value {
+                Value::Array(items) => Value::String(items.iter().map(|item| display(item, &separator)).collect::<Vec<_>>().join(&separator)),
+                value => value,
+            }
+        },
+        "default" => match truthy(&value) || matches!(value, Value::Number(_) | Value::Bool(_)) {
+            true => value,
+            false => argument(0).cloned().unwrap_or(Value::Null),
+        },
+        "length" => Value::from(match &value {
+            Value::Array(items) => items.len(),
+            Value::Object(map) => map.len(),
+            Value::Null => 0,
+            value => display(value, separator).chars().count(),
+        }),
+        _ => value,
+    }
+}
+
+// Numbers compare as numbers, including text holding numbers, anything else as text
+fn compare(left: &Value, right: &Value, separator: &str) -> Option<Ordering> {
+    match (number(left), number(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        _ if left.is_null() || right.is_null() => (left.is_null() && right.is_null()).then_some(Ordering::Equal),
+        _ => Some(display(left, separator).cmp(&display(right, separator))),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// Whole numbers are integers so they are written without a fraction
+fn number_value(number: f64) -> Value {
+    match number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        true => Value::from(number as i64),
+        false => Value::from(number),
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+// Text of a value. Lists are joined by the separator and objects with a name, like players, are written as their name
+fn display(value: &Value, separator: &str) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(|item| display(item, separator)).collect::<Vec<_>>().join(separator),
+        Value::Object(map) => match map.get("name") {
+            Some(name) => display(name, separator),
+            None => value.to_string(),
+        },
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> Context {
+        let mut context = Context::new("\n");
+        context.insert("host", "mc.example.net");
+        context.insert("hostname", "example.net");
+        context.insert("online", 2);
+        context.insert("max", 20);
+        context.insert("version", "1.20.1");
+        context.insert("players", json!([{ "name": "Alex", "id": "a" }, { "name": "Steve", "id": "b" }]));
+        context
+    }
+
+    fn render(input: &str) -> String {
+        Template::parse(input).unwrap().render(&context(), Escape::None)
+    }
+
+    #[test]
+    fn replaces_placeholders() {
+        assert_eq!(render("%hostname %host %online/%max 100% %online_players %unknown"), "example.net mc.example.net 2/20 100% 2_players %unknown");
+        assert_eq!(render("%%online %players"), "%online Alex\nSteve");
+    }
+
+    #[test]
+    fn renders_expressions() {
+        assert_eq!(render("{{ online }}/{{max}} {{ version | upper }} {{ missing | default(\"none\") }}"), "2/20 1.20.1 none");
+        assert_eq!(render("{{ players | join(\", \") }} {{ players | length }} {{ host | truncate(2) }} {{ host | truncate(2, \"\") | upper }}"), "Alex, Steve 2 mc... MC");
+        assert_eq!(render("{{ players }}"), "Alex\nSteve");
+    }
+
+    #[test]
+    fn renders_blocks() {
+        let template = "{% if online == 0 %}empty{% elif online >= max or online > 10 %}full{% else %}{{ online }} online{% endif %}";
+        assert_eq!(render(template), "2 online");
+        assert_eq!(render("{% if not missing and version == \"1.20.1\" %}yes{% endif %}"), "yes");
+        assert_eq!(render("{% for player in players %}{{ loop.index }}. {{ player.name | lower }}{% if not loop.last %}, {% endif %}{% endfor %}"), "1. alex, 2. steve");
+        assert_eq!(render("{% for player in missing %}{{ player }}{% else %}nobody{% endfor %}"), "nobody");
+        assert_eq!(render("{# comment #}{% raw %}{{ online }} %online{% endraw %}"), "{{ online }} %online");
+    }
+
+    #[test]
+    fn escapes_values() {
+        let mut context = context();
+        context.insert("description", "__A__ @everyone <!channel> &\r\n- line-2");
+        let render = |input: &str, escape: Escape| Template::parse(input).unwrap().render(&context, escape);
+        assert_eq!(render("**%description**", Escape::Discord), "**\\_\\_A\\_\\_ @\u{200b}everyone \\<!channel> &\r\n\\- line-2**");
+        assert_eq!(render("```%description``` `{{ description }}` `", Escape::Discord), "```__A__ @everyone <!channel> &\r\n- line-2``` `__A__ @everyone <!channel> &\r\n- line-2` `");
+        assert_eq!(render("<%description>", Escape::Slack), "<__A__ @everyone &lt;!channel&gt; &amp;\r\n- line-2>");
+        assert_eq!(render("%description", Escape::Header), "__A__ @everyone <!channel> &  - line-2");
+    }
+
+    #[test]
+    fn writes_formatted_lists() {
+        let mut context = context();
+        let items = ["__x__", "Steve"].iter().map(|name| {
+            let mut item = Context::new("");
+            item.insert("name", *name);
+            item
+        }).collect();
+        let mut suffix = Context::new("");
+        suffix.insert("count", 14);
+        context.insert_formatted("players", json!([{ "name": "__x__" }, { "name": "Steve" }]), Formatted {
+            items,
+            template: Template::parse("**%name**").unwrap(),
+            separator: ", ".to_owned(),
+            suffix: Some((Template::parse(" and %count more").unwrap(), suffix)),
+        });
+        let render = |input: &str, escape: Escape| Template::parse(input).unwrap().render(&context, escape);
+        assert_eq!(render("%players", Escape::Discord), "**\\_\\_x\\_\\_**, **Steve** and 14 more");
+        assert_eq!(render("```{{ players }}```", Escape::Discord), "```**__x__**, **Steve** and 14 more```");
+        assert_eq!(render("{{ players | join(\"/\") }} {% for players in players %}{{ players }}{% endfor %}", Escape::None), "__x__/Steve __x__Steve");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(Template::parse("{{ online").is_err());
+        assert!(Template::parse("{% if online %}").is_err());
+        assert!(Template::parse("{% endif %}").is_err());
+        assert!(Template::parse("{{ online | unknown }}").is_err());
+        assert!(Template::parse("{% for in players %}{% endfor %}").is_err());
+        assert!(Template::parse("{% while %}").is_err());
+        assert_eq!(super::render("{{ %online/%max {# 100%", &context(), Escape::None), "{{ 2/20 {# 100%");
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime,
+    Days,
+    NaiveDate,
+    SecondsFormat,
+};
+use chrono_tz::Tz;
+
+use crate::{models::InternalError, state};
+
+// Parses a point in time: "now", Unix time, a duration ago like "7d" or a UTC date like "2024-05-01" or "2024-05-01T18:30"
+pub fn parse_time(value: &str) -> Result<u64, InternalError> {
+    let value = value.trim();
+    let now = state::now();
+    if value == "now" {
+        return Ok(now);
+    }
+    if let Ok(time) = value.parse::<u64>() {
+        return Ok(time);
+    }
+    let relative = value.strip_prefix('-').unwrap_or(value);
+    if relative.ends_with(|c: char| c.is_ascii_alphabetic()) && !relative.contains(':') {
+        return Ok(now.saturating_sub(parse_duration(relative)?));
+    }
+    parse_date(value).ok_or_else(|| InternalError::new(format!("'{}' is not a valid time", value)))
+}
+
+// Parses a duration in seconds, optionally followed by a unit: s, m, h, d or w
+pub fn parse_duration(value: &str) -> Result<u64, InternalError> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(InternalError::new(format!("'{}' is not a valid duration", value))),
+    };
+    number.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| InternalError::new(format!("'{}' is not a valid duration", value)))
+}
+
+fn parse_date(value: &str) -> Option<u64> {
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let (date, time) = match value.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    // Dates which don't exist, like "2024-02-31", are rejected
+    let date = NaiveDate::from_ymd_opt(year.try_into().ok()?, month, day)?;
+    let (hours, minutes, seconds) = match time {
+        Some(time) => {
+            let parts = time.split(':').map(str::parse::<u32>).collect::<Result<Vec<_>, _>>().ok()?;
+            match parts.as_slice() {
+                [hours, minutes] => (*hours, *minutes, 0),
+                [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+                _ => return None,
+            }
+        },
+        None => (0, 0, 0),
+    };
+    date.and_hms_opt(hours, minutes, seconds)?.and_utc().timestamp().try_into().ok()
+}
+
+// Formats Unix time as a UTC date and time, e.g. "2024-05-01 18:30:00"
+pub fn format_time(time: u64) -> String {
+    let (year, month, day) = civil_from_days((time / 86400) as i64);
+    let seconds = time % 86400;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+// Formats Unix time in the time zone with a strftime format, which must have been checked by is_valid_format
+pub fn format_local(time: u64, timezone: Tz, format: &str) -> String {
+    local(time, timezone).format(format).to_string()
+}
+
+// Formats Unix time in the time zone as ISO 8601, e.g. "2024-05-01T20:30:15+02:00"
+pub fn format_iso(time: u64, timezone: Tz) -> String {
+    local(time, timezone).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// Unix time in the time zone
+pub fn local(time: u64, timezone: Tz) -> DateTime<Tz> {
+    DateTime::from_timestamp(time as i64, 0).unwrap_or_default().with_timezone(&timezone)
+}
+
+// Unix time a number of calendar days earlier at the same local time, so days last 23 or 25 hours when the clocks change
+pub fn days_before(time: u64, days: u64, timezone: Tz) -> u64 {
+    local(time, timezone)
+        .naive_local()
+        .checked_sub_days(Days::new(days))
+        .and_then(|date| date.and_local_timezone(timezone).earliest())
+        .and_then(|date| date.timestamp().try_into().ok())
+        .unwrap_or_else(|| time.saturating_sub(days.saturating_mul(86400)))
+}
+
+// Whether every specifier of the strftime format is known, as formatting fails on the others
+pub fn is_valid_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| item == Item::Error)
+}
+
+// Formats a duration in seconds with its two largest units, e.g. "2h 5m"
+pub fn format_duration(seconds: u64) -> String {
+    let parts = [(seconds / 86400, "d"), (seconds / 3600 % 24, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")];
+    let first = parts.iter().position(|(value, _)| *value > 0).unwrap_or(parts.len() - 1);
+    let mut text = format!("{}{}", parts[first].0, parts[first].1);
+    if let Some((value, unit)) = parts.get(first + 1).filter(|(value, _)| *value > 0) {
+        text.push_str(&format!(" {}{}", value, unit));
+    }
+    text
+}
+
+// Proleptic Gregorian date of a number of days since the Unix epoch
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_time("1714588200").unwrap(), 1714588200);
+        assert_eq!(parse_time("2024-05-01").unwrap(), 1714521600);
+        assert_eq!(parse_time("2024-05-01T18:30").unwrap(), 1714588200);
+        assert_eq!(parse_time("2024-05-01 18:30:15Z").unwrap(), 1714588215);
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("2024-02-31").is_err());
+        assert!(parse_time("2024-05-01T24:00").is_err());
+        assert!(parse_time("1969-12-31").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = state::now();
+        let time = parse_time("7d").unwrap();
+        assert!(time <= now - 7 * 86400 && time + 5 >= now - 7 * 86400);
+        assert_eq!(parse_duration("5m").unwrap(), 300);
+        assert_eq!(parse_duration("90").unwrap(), 90);
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+        assert!(parse_time("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn counts_calendar_days() {
+        // Midnight of 2024-04-01 in Warsaw, the day after the clocks went forward
+        assert_eq!(days_before(1711922400, 1, "Europe/Warsaw".parse().unwrap()), 1711922400 - 23 * 3600);
+        assert_eq!(days_before(1711922400, 1, Tz::UTC), 1711922400 - 86400);
+    }
+
+    #[test]
+    fn formats_local_times() {
+        let warsaw = "Europe/Warsaw".parse::<Tz>().unwrap();
+        assert_eq!(format_local(1714588215, warsaw, "%d.%m.%Y %H:%M"), "01.05.2024 20:30");
+        assert_eq!(format_local(1704112215, warsaw, "%H:%M %Z"), "13:30 CET");
+        assert_eq!(format_iso(1714588215, "America/New_York".parse().unwrap()), "2024-05-01T14:30:15-04:00");
+        assert_eq!(format_iso(1714588215, Tz::UTC), "2024-05-01T18:30:15Z");
+        assert!(is_valid_format("%Y-%m-%d %H:%M:%S"));
+        assert!(!is_valid_format("%Q"));
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_time(1714588215), "2024-05-01 18:30:15");
+        assert_eq!(format_time(951782400), "2000-02-29 00:00:00");
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(312), "5m 12s");
+        assert_eq!(format_duration(7200), "2h");
+        assert_eq!(format_duration(2 * 86400 + 3 * 3600 + 59), "2d 3h");
+    }
+}